@@ -0,0 +1,248 @@
+/// Named profiles from the config file, ie. ~/.config/auth-token/config.toml
+use auth_token::okta::OktaClientError;
+use clap::{ArgMatches, ValueSource};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+/// Values for the flags of the same name
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub base_url: Option<String>,
+    pub client_id: Option<String>,
+    pub authorization_server_id: Option<String>,
+    pub issuer: Option<String>,
+    pub login_redirect_url: Option<String>,
+    pub scopes: Option<String>,
+    pub username: Option<String>,
+    pub flow: Option<String>,
+    pub response_type: Option<String>,
+    pub response_mode: Option<String>,
+    pub factor: Option<String>,
+    pub totp_seed: Option<String>,
+    pub copy_to_clipboard: Option<bool>,
+}
+
+impl Profile {
+    fn get(&self, name: &str) -> Option<&String> {
+        match name {
+            "base-url" => self.base_url.as_ref(),
+            "client-id" => self.client_id.as_ref(),
+            "authorization-server-id" => self.authorization_server_id.as_ref(),
+            "issuer" => self.issuer.as_ref(),
+            "login-redirect-url" => self.login_redirect_url.as_ref(),
+            "scopes" => self.scopes.as_ref(),
+            "username" => self.username.as_ref(),
+            "flow" => self.flow.as_ref(),
+            "response-type" => self.response_type.as_ref(),
+            "response-mode" => self.response_mode.as_ref(),
+            "factor" => self.factor.as_ref(),
+            "totp-seed" => self.totp_seed.as_ref(),
+            _ => None,
+        }
+    }
+
+    fn is_set(&self, name: &str) -> bool {
+        match name {
+            "copy-to-clipboard" => self.copy_to_clipboard.unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("auth-token").join("config.toml"))
+    }
+
+    pub fn load(path: &PathBuf) -> Result<Self, OktaClientError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| OktaClientError::Configuration(format!("{}: {}", path.display(), e)))?;
+
+        toml::from_str(&text)
+            .map_err(|e| OktaClientError::Configuration(format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// Flags, falling back to the selected profile, then to the flag's default value
+pub struct Options<'a> {
+    args: &'a ArgMatches,
+    profile: Profile,
+}
+
+impl<'a> Options<'a> {
+    /// Read the profile named by --profile, from the file given by --config or the default config file
+    pub fn new(args: &'a ArgMatches) -> Result<Self, OktaClientError> {
+        let profile = match args.value_of("profile") {
+            Some(name) => {
+                let path = args
+                    .value_of("config")
+                    .map(PathBuf::from)
+                    .or_else(Config::default_path)
+                    .ok_or_else(|| {
+                        OktaClientError::Configuration("No config directory found".to_owned())
+                    })?;
+
+                Config::load(&path)?.profiles.remove(name).ok_or_else(|| {
+                    OktaClientError::Configuration(format!(
+                        "Profile {} not found in {}",
+                        name,
+                        path.display()
+                    ))
+                })?
+            }
+            None => Profile::default(),
+        };
+
+        Ok(Options { args, profile })
+    }
+
+    pub fn value_of(&self, name: &str) -> Option<String> {
+        if self.args.value_source(name) == Some(ValueSource::CommandLine) {
+            return self.args.value_of(name).map(|s| s.to_owned());
+        }
+
+        self.profile
+            .get(name)
+            .map(|s| s.to_owned())
+            .or_else(|| self.args.value_of(name).map(|s| s.to_owned()))
+    }
+
+    /// The issuer, unless --authorization-server-id was given to override the profile's issuer
+    pub fn issuer(&self) -> Option<String> {
+        match self.args.value_source("authorization-server-id") {
+            Some(ValueSource::CommandLine) => None,
+            _ => self.value_of("issuer"),
+        }
+    }
+
+    /// Read a value that must be given by a flag or the profile
+    pub fn required(&self, name: &str) -> Result<String, OktaClientError> {
+        self.value_of(name)
+            .ok_or_else(|| OktaClientError::Configuration(format!("--{} is required", name)))
+    }
+
+    pub fn is_present(&self, name: &str) -> bool {
+        self.args.is_present(name) || self.profile.is_set(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{App, Arg};
+
+    fn matches(args: &[&str]) -> ArgMatches {
+        App::new("test")
+            .arg(Arg::new("base-url").long("base-url").takes_value(true))
+            .arg(
+                Arg::new("authorization-server-id")
+                    .long("authorization-server-id")
+                    .default_value("default"),
+            )
+            .arg(Arg::new("issuer").long("issuer").takes_value(true))
+            .arg(Arg::new("copy-to-clipboard").long("copy-to-clipboard"))
+            .get_matches_from([&["test"], args].concat())
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            base_url: Some("https://profile.okta.com".to_owned()),
+            authorization_server_id: Some("profile-server".to_owned()),
+            copy_to_clipboard: Some(true),
+            ..Profile::default()
+        }
+    }
+
+    #[test]
+    fn prefers_the_command_line_then_the_profile_then_the_default() {
+        let args = matches(&[
+            "--base-url",
+            "https://cli.okta.com",
+            "--authorization-server-id",
+            "cli-server",
+        ]);
+        let options = Options {
+            args: &args,
+            profile: profile(),
+        };
+        assert_eq!(
+            options.value_of("base-url").as_deref(),
+            Some("https://cli.okta.com")
+        );
+        assert_eq!(
+            options.value_of("authorization-server-id").as_deref(),
+            Some("cli-server")
+        );
+
+        let args = matches(&[]);
+        let options = Options {
+            args: &args,
+            profile: profile(),
+        };
+        assert_eq!(
+            options.value_of("base-url").as_deref(),
+            Some("https://profile.okta.com")
+        );
+        assert_eq!(
+            options.value_of("authorization-server-id").as_deref(),
+            Some("profile-server")
+        );
+        assert!(options.is_present("copy-to-clipboard"));
+
+        let options = Options {
+            args: &args,
+            profile: Profile::default(),
+        };
+        assert_eq!(options.value_of("base-url"), None);
+        assert_eq!(
+            options.value_of("authorization-server-id").as_deref(),
+            Some("default")
+        );
+        assert!(!options.is_present("copy-to-clipboard"));
+        assert!(matches!(
+            options.required("base-url"),
+            Err(OktaClientError::Configuration(_))
+        ));
+    }
+
+    #[test]
+    fn overrides_the_profile_issuer_with_an_authorization_server_id() {
+        let profile = Profile {
+            issuer: Some("https://profile.okta.com/oauth2/profile-server".to_owned()),
+            ..Profile::default()
+        };
+
+        let args = matches(&[]);
+        let options = Options {
+            args: &args,
+            profile: profile.to_owned(),
+        };
+        assert_eq!(
+            options.issuer().as_deref(),
+            Some("https://profile.okta.com/oauth2/profile-server")
+        );
+
+        let args = matches(&["--authorization-server-id", "cli-server"]);
+        let options = Options {
+            args: &args,
+            profile: profile.to_owned(),
+        };
+        assert_eq!(options.issuer(), None);
+
+        let args = matches(&["--issuer", "https://cli.okta.com"]);
+        let options = Options {
+            args: &args,
+            profile,
+        };
+        assert_eq!(options.issuer().as_deref(), Some("https://cli.okta.com"));
+    }
+}
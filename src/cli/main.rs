@@ -1,97 +1,718 @@
-use auth_token::okta::{OktaClient, OktaClientError};
-use clap::{App, Arg};
+use auth_token::okta::{
+    default_secret_store, password_secret_name, username_secret_name, Browser, CachedTokens,
+    ChallengeAnswer, ClientAuthentication, FactorType, Flow, MfaPrompt, OktaClient,
+    OktaClientBuilder, OktaClientError, OktaFactor, PasswordPrompt, PushStatus, ResponseMode,
+    ResponseType, SecretStore, SystemBrowser, TokenCache, TokenIntrospection, TokenResponse,
+    TokenTypeHint,
+};
+use clap::{App, Arg, ArgMatches};
 use clipboard::ClipboardContext;
 use clipboard::ClipboardProvider;
 use colored::*; // TODO narrow scope
+use std::cell::RefCell;
+use std::sync::Arc;
 
-fn main() {
+mod config;
+
+use config::Options;
+
+/// Environment variable for --totp-seed, so the seed isn't visible in the process list
+const TOTP_SEED_ENV: &str = "AUTH_TOKEN_TOTP_SEED";
+
+#[tokio::main]
+async fn main() {
     let matches = App::new("auth-token")
         .version(env!("CARGO_PKG_VERSION"))
         .author("Kye Lewis <kye.lewis@exogee.com>")
         .about("A CLI tool to get an access token for use in development.")
-         .subcommand(App::new("okta-access-token").help("Returns an OKTA access token")
-        .arg(
-            Arg::new("base-url")
-                .long("base-url")
-                .value_name("base-url")
-                .help("Base URL of the OKTA Tenant (ie. https://myapp.okta.com/)")
-                .required(true),
-        )
-        .arg(
-            Arg::new("client-id")
-                .value_name("client-id")
-                .long("client-id")
-                .help("The OKTA Client ID associated with the app")
-                .required(true),
+        .subcommand(
+            App::new("okta-access-token")
+                .about("Returns an OKTA access token")
+                .args(tenant_args())
+                .args(output_args())
+                .arg(
+                    Arg::new("flow")
+                        .long("flow")
+                        .value_name("flow")
+                        .help("How to sign in: authn (session token and auth code), password (password grant), idx (Identity Engine) or browser (system browser)")
+                        .possible_values(Flow::NAMES)
+                        .default_value("authn"),
+                )
+                .arg(
+                    Arg::new("browser")
+                        .long("browser")
+                        .takes_value(false)
+                        .help("Sign in with the system browser, for SSO, WebAuthn or FastPass (same as --flow browser, the login redirect URL must be http://localhost with a port)"),
+                )
+                .arg(
+                    Arg::new("login-redirect-url")
+                        .long("login-redirect-url")
+                        .value_name("login-redirect-url")
+                        .help("OKTA Login Redirect URL associated with the app (not used by the password flow)")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("scopes")
+                        .long("scopes")
+                        .value_name("scopes")
+                        .help("The scope(s) to request (ie. openid profile email)")
+                        .default_value("openid profile email"),
+                )
+                .arg(
+                    Arg::new("username")
+                        .long("username")
+                        .value_name("username")
+                        .help("OKTA username (optional, prompted on CLI if omitted)")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("password")
+                        .long("password")
+                        .value_name("password")
+                        .help("OKTA password (optional, prompted on CLI if omitted)")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("totp-code")
+                        .long("totp-code")
+                        .value_name("totp-code")
+                        .help("Passcode from Google Authenticator or Okta Verify, if MFA is required (optional, prompted on CLI if omitted)")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("totp-seed")
+                        .long("totp-seed")
+                        .value_name("totp-seed")
+                        .help("Base32 seed of a Google Authenticator or Okta Verify factor, to generate passcodes instead of asking for them (optional, or set AUTH_TOKEN_TOTP_SEED)")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("response-type")
+                        .long("response-type")
+                        .value_name("response-type")
+                        .help("What the authn flow gets from the authorize endpoint: code, or token and id_token for apps that use the implicit grant")
+                        .possible_values(ResponseType::NAMES)
+                        .default_value("code"),
+                )
+                .arg(
+                    Arg::new("response-mode")
+                        .long("response-mode")
+                        .value_name("response-mode")
+                        .help("How the authn flow gets the authorize response: form_post, or the redirect's query or fragment")
+                        .possible_values(ResponseMode::NAMES)
+                        .default_value("form_post"),
+                )
+                .arg(
+                    Arg::new("factor")
+                        .long("factor")
+                        .value_name("factor")
+                        .help("The MFA factor to verify when several are enrolled (optional, defaults to the first of totp, push, sms, call and email)")
+                        .possible_values(FactorType::NAMES)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("no-cache")
+                        .long("no-cache")
+                        .value_name("no-cache")
+                        .takes_value(false)
+                        .help("Always sign in again, instead of using or storing cached tokens"),
+                ),
         )
-        .arg(
-            Arg::new("authorization-server-id")
-                .value_name("authorization-server-id")
-                .long("authorization-server-id")
-                .help(
-                    "If using a custom Authorization Server, the ID for that authorization server",
-                )
-                .default_value("default"),
+        .subcommand(
+            App::new("okta-device-login")
+                .about("Returns an OKTA access token after signing in on another device, ie. a phone")
+                .args(tenant_args())
+                .args(output_args())
+                .arg(
+                    Arg::new("scopes")
+                        .long("scopes")
+                        .value_name("scopes")
+                        .help("The scope(s) to request (ie. openid profile email offline_access)")
+                        .default_value("openid profile email"),
+                ),
         )
-        .arg(
-            Arg::new("copy-to-clipboard")
-                .long("copy-to-clipboard")
-                .value_name("copy-to-clipboard")
-                .takes_value(false)
-                .help("Copy the result to the system clipboard"),
+        .subcommand(
+            App::new("okta-refresh-token")
+                .about("Exchanges an OKTA refresh token for a new access token")
+                .args(tenant_args())
+                .args(output_args())
+                .arg(
+                    Arg::new("scopes")
+                        .long("scopes")
+                        .value_name("scopes")
+                        .help("The scope(s) to request (optional, defaults to the scopes of the original grant)")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("refresh-token")
+                        .long("refresh-token")
+                        .value_name("refresh-token")
+                        .help("The refresh token (optional, prompted on CLI if omitted)")
+                        .required(false),
+                ),
         )
-        .arg(
-            Arg::new("print-token-json")
-                .long("print-token-json")
-                .value_name("print-token-json")
-                .takes_value(false)
-                .help("Print the JSON of the token to stdout instead of the token itself"),
+        .subcommand(
+            App::new("okta-client-credentials")
+                .about("Returns an OKTA access token for the app itself, for service-to-service calls")
+                .args(tenant_args())
+                .args(output_args())
+                .arg(
+                    Arg::new("scopes")
+                        .long("scopes")
+                        .value_name("scopes")
+                        .help("The custom scope(s) to request")
+                        .required(false),
+                )
+                .args(client_authentication_args("The OKTA Client Secret (optional, prompted on CLI if omitted)")),
         )
-        .arg(
-            Arg::new("login-redirect-url")
-                .long("login-redirect-url")
-                .value_name("login-redirect-url")
-                .help("OKTA Login Redirect URL associated with the app")
-                .required(true),
+        .subcommand(
+            App::new("revoke")
+                .about("Revokes an OKTA access or refresh token, ie. one that was shared by mistake, or every token in the token cache")
+                .args(tenant_args())
+                .args(client_authentication_args("The OKTA Client Secret, if the app is confidential (optional)"))
+                .arg(
+                    Arg::new("token")
+                        .long("token")
+                        .value_name("token")
+                        .help("The access or refresh token to revoke (optional, prompted on CLI if omitted)")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("token-type-hint")
+                        .long("token-type-hint")
+                        .value_name("token-type-hint")
+                        .help("Whether the token is an access_token or refresh_token (optional)")
+                        .possible_values(TokenTypeHint::NAMES)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("all-cached")
+                        .long("all-cached")
+                        .takes_value(false)
+                        .conflicts_with_all(&["token", "token-type-hint"])
+                        .help("Revoke every token in the token cache, then remove them from it"),
+                ),
         )
-        .arg(
-            Arg::new("scopes")
-                .long("scopes")
-                .value_name("scopes")
-                .help("The scope(s) to request (ie. openid profile email)")
-                .default_value("openid profile email"),
+        .subcommand(
+            App::new("introspect")
+                .about("Checks whether an OKTA access or refresh token is still active on the authorization server, ie. it hasn't been revoked")
+                .args(tenant_args())
+                .args(client_authentication_args("The OKTA Client Secret, if the app is confidential (optional)"))
+                .arg(
+                    Arg::new("token")
+                        .long("token")
+                        .value_name("token")
+                        .help("The access or refresh token to check (optional, prompted on CLI if omitted)")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("token-type-hint")
+                        .long("token-type-hint")
+                        .value_name("token-type-hint")
+                        .help("Whether the token is an access_token or refresh_token (optional)")
+                        .possible_values(TokenTypeHint::NAMES)
+                        .required(false),
+                ),
         )
-        .arg(
-            Arg::new("username")
-                .long("username")
-                .value_name("username")
-                .help("OKTA username (optional, prompted on CLI if omitted)")
-                .required(false),
+        .subcommand(
+            App::new("credentials")
+                .about("Manages OKTA credentials saved in the OS keyring, or an encrypted file if it is unavailable")
+                .subcommand_required(true)
+                .subcommand(
+                    App::new("set")
+                        .about("Saves a username and password, so they are not prompted for")
+                        .arg(credentials_base_url_arg())
+                        .arg(
+                            Arg::new("username")
+                                .long("username")
+                                .value_name("username")
+                                .help("OKTA username (optional, prompted on CLI if omitted)")
+                                .required(false),
+                        )
+                        .arg(
+                            Arg::new("password")
+                                .long("password")
+                                .value_name("password")
+                                .help("OKTA password (optional, prompted on CLI if omitted)")
+                                .required(false),
+                        ),
+                )
+                .subcommand(
+                    App::new("clear")
+                        .about("Removes a saved username and password")
+                        .arg(credentials_base_url_arg())
+                        .arg(
+                            Arg::new("username")
+                                .long("username")
+                                .value_name("username")
+                                .help("OKTA username (optional, defaults to the saved username)")
+                                .required(false),
+                        ),
+                ),
         )
-        .arg(
-            Arg::new("password")
-                .long("password")
-                .value_name("password")
-                .help("OKTA password (optional, prompted on CLI if omitted)")
-                .required(false),
-         ))
         .get_matches();
 
     eprintln!("🎉 auth-token - A CLI tool to get an access token for use in development.");
 
     match matches.subcommand() {
-        Some(("okta-access-token", args)) => {
-            // Read Base URL, Redirect URL and Client ID from flags.
+        Some(("okta-access-token", args)) => okta_access_token(&options(args)).await,
+        Some(("okta-device-login", args)) => okta_device_login(&options(args)).await,
+        Some(("okta-refresh-token", args)) => okta_refresh_token(&options(args)).await,
+        Some(("okta-client-credentials", args)) => okta_client_credentials(&options(args)).await,
+        Some(("revoke", args)) => revoke(&options(args)).await,
+        Some(("introspect", args)) => introspect(&options(args)).await,
+        Some(("credentials", args)) => credentials(args),
+        _ => {
+            println!("Run with --help for usage.")
+        }
+    }
+}
+
+async fn okta_access_token(options: &Options<'_>) {
+    // Read Base URL, Redirect URL and Client ID from flags or the profile.
+    let url = base_url(options).unwrap_or_else(|e| show_error(e));
+    let flow = if options.is_present("browser") {
+        Flow::Browser
+    } else {
+        options
+            .required("flow")
+            .and_then(|flow| flow.parse::<Flow>().map_err(OktaClientError::Configuration))
+            .unwrap_or_else(|e| show_error(e))
+    };
+    let login_redirect_url = match (options.value_of("login-redirect-url"), flow) {
+        (Some(login_redirect_url), _) => Some(login_redirect_url),
+        (None, Flow::Password) => None,
+        (None, flow) => show_error(OktaClientError::Configuration(format!(
+            "--login-redirect-url is required for the {} flow",
+            flow
+        ))),
+    };
+    let scopes = options.required("scopes").unwrap_or_else(|e| show_error(e));
+    let response_type = options
+        .required("response-type")
+        .and_then(|response_type| {
+            response_type
+                .parse::<ResponseType>()
+                .map_err(OktaClientError::Configuration)
+        })
+        .unwrap_or_else(|e| show_error(e));
+    let response_mode = options
+        .required("response-mode")
+        .and_then(|response_mode| {
+            response_mode
+                .parse::<ResponseMode>()
+                .map_err(OktaClientError::Configuration)
+        })
+        .unwrap_or_else(|e| show_error(e));
+    let factor = options
+        .value_of("factor")
+        .map(|factor| factor.parse::<FactorType>())
+        .transpose()
+        .map_err(OktaClientError::Configuration)
+        .unwrap_or_else(|e| show_error(e));
+
+    let secret_store = LazySecretStore::default();
+
+    // Read Username and Password from flags or the profile, if provided, then saved credentials, otherwise read from CLI.
+    // The browser asks for them itself.
+    let username = match flow {
+        Flow::Browser => String::new(),
+        _ => options
+            .value_of("username")
+            .or_else(|| get_secret(secret_store.open().as_ref(), &username_secret_name(&url)))
+            .unwrap_or_else(|| read_input("Username? (hidden) ".to_owned())),
+    };
+
+    // Use the cached tokens if the access token is still valid, or refresh them, before asking for a password.
+    let cache = if options.is_present("no-cache") {
+        None
+    } else {
+        token_cache(secret_store.open().as_ref())
+    };
+
+    // Everything but the password, which is only asked for if the cached tokens can't be used.
+    let builder = || {
+        let mut builder = tenant_builder(options)
+            .scopes(scopes.split_whitespace())
+            .flow(flow)
+            .response_type(response_type)
+            .response_mode(response_mode)
+            .mfa_prompt(CliMfaPrompt {
+                totp_code: options.value_of("totp-code"),
+            })
+            .password_prompt(CliPasswordPrompt);
+
+        builder = match flow {
+            Flow::Browser => builder.browser(CliBrowser),
+            _ => builder.username(username.to_owned()),
+        };
+
+        if let Some(login_redirect_url) = &login_redirect_url {
+            builder = builder.login_redirect_url(login_redirect_url.to_owned());
+        }
+
+        if let Some(factor) = factor {
+            builder = builder.factor(factor);
+        }
+
+        if let Some(totp_seed) = options
+            .value_of("totp-seed")
+            .or_else(|| std::env::var(TOTP_SEED_ENV).ok())
+        {
+            builder = builder.totp_seed(totp_seed);
+        }
+
+        builder
+    };
+
+    if let Some(cache) = &cache {
+        let key = builder().cache_key().unwrap_or_else(|e| show_error(e));
+
+        // A cache that can't be read is skipped, the same as a missing secret
+        let cached = cache.get(&key).map_err(|e| show_warning(&e)).ok().flatten();
+
+        if let Some(cached) = cached {
+            if !cached.is_expired() {
+                match flow {
+                    Flow::Browser => eprintln!("🔐 Using cached Access Token"),
+                    _ => eprintln!(
+                        "🔐 Using cached Access Token for {}",
+                        username.to_owned().underline()
+                    ),
+                }
+
+                if options.is_present("verify") {
+                    let client = builder().build().await.unwrap_or_else(|e| show_error(e));
+                    verify_tokens(&client, &cached.tokens, options).await;
+                }
+
+                return output_tokens(&cached.tokens, options);
+            }
+
+            // Refresh the expired access token before asking for a password
+            if cached.tokens.refresh_token.is_some() {
+                eprintln!("🔐 Refreshing cached Access Token");
+
+                let client = builder().build().await.unwrap_or_else(|e| show_error(e));
+
+                match client.get_cached_tokens(cache).await {
+                    Ok(Some(tokens)) => {
+                        verify_tokens(&client, &tokens, options).await;
+                        return output_tokens(&tokens, options);
+                    }
+                    Ok(None) => eprintln!("🔐 The refresh token has expired, signing in again"),
+                    Err(e) => show_warning(&e),
+                }
+            }
+        }
+    }
+
+    let client = match flow {
+        Flow::Browser => {
+            eprintln!("🌐 Signing in with your browser");
+            builder()
+        }
+        _ => {
+            let password = options
+                .value_of("password")
+                .or_else(|| {
+                    get_secret(
+                        secret_store.open().as_ref(),
+                        &password_secret_name(&url, &username),
+                    )
+                })
+                .unwrap_or_else(|| read_input("Password? (hidden) ".to_owned()));
+
+            eprintln!(
+                "🔐 Getting Access Token for {}",
+                username.to_owned().underline()
+            );
+
+            builder().credentials(username.to_owned(), password)
+        }
+    }
+    .build()
+    .await
+    .unwrap_or_else(|e| show_error(e));
+
+    // The cache was already checked, so sign in and replace the cached tokens.
+    let tokens = client.get_tokens().await.unwrap_or_else(|e| show_error(e));
+
+    if let Some(cache) = &cache {
+        cache
+            .put(&client.cache_key(), &tokens)
+            .unwrap_or_else(|e| show_warning(&e));
+    }
+
+    verify_tokens(&client, &tokens, options).await;
+    output_tokens(&tokens, options)
+}
+
+async fn okta_device_login(options: &Options<'_>) {
+    let scopes = options.required("scopes").unwrap_or_else(|e| show_error(e));
+
+    let client = tenant_builder(options)
+        .scopes(scopes.split_whitespace())
+        .build()
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    let authorization = client
+        .device_authorization()
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    eprintln!(
+        "📱 To sign in, visit {} and enter the code {}",
+        authorization.verification_uri.underline(),
+        authorization.user_code.bold()
+    );
+    if let Some(uri) = &authorization.verification_uri_complete {
+        eprintln!("🌐 Or open {}", uri.underline());
+    }
+    eprintln!("⏳ Waiting for you to sign in");
+
+    let tokens = client
+        .get_tokens_with_device(&authorization)
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    eprintln!("✅ Signed in");
+
+    verify_tokens(&client, &tokens, options).await;
+    output_tokens(&tokens, options)
+}
+
+async fn okta_refresh_token(options: &Options<'_>) {
+    let scopes = options.value_of("scopes").unwrap_or_default();
+
+    let refresh_token = options
+        .value_of("refresh-token")
+        .unwrap_or_else(|| read_input("Refresh Token? (hidden) ".to_owned()));
+
+    eprintln!("🔐 Refreshing Access Token");
+
+    let client = tenant_builder(options)
+        .scopes(scopes.split_whitespace())
+        .build()
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    let tokens = client
+        .refresh(refresh_token)
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    verify_tokens(&client, &tokens, options).await;
+    output_tokens(&tokens, options)
+}
+
+async fn okta_client_credentials(options: &Options<'_>) {
+    let client_id = options
+        .required("client-id")
+        .unwrap_or_else(|e| show_error(e));
+    let scopes = options.required("scopes").unwrap_or_else(|e| show_error(e));
+
+    // Read the private key or client secret from flags, otherwise the client secret from CLI.
+    let client_authentication = client_authentication(options).unwrap_or_else(|| {
+        ClientAuthentication::ClientSecret(read_input("Client Secret? (hidden) ".to_owned()))
+    });
+
+    eprintln!(
+        "🔐 Getting Access Token for client {}",
+        client_id.underline()
+    );
+
+    let client = tenant_builder(options)
+        .scopes(scopes.split_whitespace())
+        .client_authentication(client_authentication)
+        .build()
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    let tokens = client
+        .client_credentials()
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    verify_tokens(&client, &tokens, options).await;
+    output_tokens(&tokens, options)
+}
+
+async fn revoke(options: &Options<'_>) {
+    if options.is_present("all-cached") {
+        return revoke_cached().await;
+    }
+
+    let (token, token_type_hint) = token_with_hint(options);
+    let client = confidential_builder(options)
+        .build()
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    eprintln!("🔐 Revoking Token");
+
+    client
+        .revoke(&token, token_type_hint)
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    eprintln!("✅  {}", "Token Revoked".green().bold());
+}
+
+/// Revoke every token in the token cache, removing those that were revoked
+async fn revoke_cached() {
+    let cache = token_cache(secret_store().as_ref()).unwrap_or_else(|| {
+        show_error(OktaClientError::Cache(
+            "No cache directory found".to_owned(),
+        ))
+    });
+    let entries = cache.entries().unwrap_or_else(|e| show_error(e));
+
+    let mut failed = 0;
+    for entry in &entries {
+        let key = &entry.key;
+        let name = match key.username.as_str() {
+            "" => format!("client {}", key.client_id),
+            username => username.to_owned(),
+        };
+
+        eprintln!(
+            "🔐 Revoking Tokens for {} at {}",
+            name.underline(),
+            key.base_url
+        );
+
+        match revoke_cached_tokens(entry).await {
+            Ok(()) => cache.remove(key).unwrap_or_else(|e| show_error(e)),
+            Err(e) => {
+                show_warning(&e);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        show_error(OktaClientError::General(format!(
+            "{} of {} cached tokens couldn't be revoked, they are still cached",
+            failed,
+            entries.len()
+        )));
+    }
+
+    eprintln!(
+        "✅  {}",
+        format!("{} Cached Tokens Revoked", entries.len())
+            .green()
+            .bold()
+    );
+}
+
+/// Revoke the refresh token first, as it also revokes the access tokens issued with it
+async fn revoke_cached_tokens(cached: &CachedTokens) -> Result<(), OktaClientError> {
+    let key = &cached.key;
+    let builder = OktaClient::builder()
+        .base_url(&key.base_url)
+        .client_id(&key.client_id);
+
+    // The cache key has the issuer URL in place of the ID if --issuer was given
+    let builder = if key.authorization_server_id.starts_with("http://")
+        || key.authorization_server_id.starts_with("https://")
+    {
+        builder.issuer(&key.authorization_server_id)
+    } else {
+        builder.authorization_server_id(&key.authorization_server_id)
+    };
+
+    let client = builder.build().await?;
+
+    if let Some(refresh_token) = &cached.tokens.refresh_token {
+        client
+            .revoke(refresh_token, Some(TokenTypeHint::RefreshToken))
+            .await?;
+    }
+
+    if !cached.tokens.access_token.is_empty() {
+        client
+            .revoke(
+                &cached.tokens.access_token,
+                Some(TokenTypeHint::AccessToken),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+async fn introspect(options: &Options<'_>) {
+    let (token, token_type_hint) = token_with_hint(options);
+    let client = confidential_builder(options)
+        .build()
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    eprintln!("🔍 Introspecting Token");
+
+    let introspection = client
+        .introspect(&token, token_type_hint)
+        .await
+        .unwrap_or_else(|e| show_error(e));
+
+    output_introspection(&introspection);
+}
+
+/// Print the fields of an introspection response that are set, and whether the token is active
+fn output_introspection(introspection: &TokenIntrospection) {
+    println!("active: {}", introspection.active);
+
+    if let Some(exp) = introspection.exp {
+        match chrono::NaiveDateTime::from_timestamp_opt(exp, 0) {
+            Some(expires) => println!("exp: {} ({} UTC)", exp, expires),
+            None => println!("exp: {}", exp),
+        }
+    }
+
+    for (name, value) in [
+        ("scope", &introspection.scope),
+        ("sub", &introspection.sub),
+        ("client_id", &introspection.client_id),
+    ] {
+        if let Some(value) = value {
+            println!("{}: {}", name, value);
+        }
+    }
+
+    match introspection.active {
+        true => eprintln!("✅  {}", "Token Is Active".green().bold()),
+        false => eprintln!(
+            "⚠️  {} The token has expired, been revoked or is from another authorization server",
+            "Inactive:".yellow().bold()
+        ),
+    }
+}
+
+/// The token to revoke or introspect and its type hint, from flags or CLI
+fn token_with_hint(options: &Options) -> (String, Option<TokenTypeHint>) {
+    let token = options
+        .value_of("token")
+        .unwrap_or_else(|| read_input("Token? (hidden) ".to_owned()));
+    let token_type_hint = options
+        .value_of("token-type-hint")
+        .map(|token_type_hint| token_type_hint.parse::<TokenTypeHint>())
+        .transpose()
+        .map_err(OktaClientError::Configuration)
+        .unwrap_or_else(|e| show_error(e));
+
+    (token, token_type_hint)
+}
+
+fn credentials(args: &ArgMatches) {
+    let secret_store = default_secret_store().unwrap_or_else(|e| show_error(e));
+
+    match args.subcommand() {
+        Some(("set", args)) => {
             let url = args.value_of("base-url").unwrap().to_owned();
-            let login_redirect_url = args.value_of("login-redirect-url").unwrap().to_owned();
-            let client_id = args.value_of("client-id").unwrap().to_owned();
-            let authorization_server_id =
-                args.value_of("authorization-server-id").unwrap().to_owned();
-            let scopes = args.value_of("scopes").unwrap().to_owned();
-            let copy_to_clipboard = args.is_present("copy-to-clipboard");
-            let print_token_json = args.is_present("print-token-json");
-
-            // Read Username and Password from flags, if provided, otherwise read from CLI.
+
             let username = args
                 .value_of("username")
                 .map(|s| s.to_owned())
@@ -102,100 +723,467 @@ fn main() {
                 .map(|s| s.to_owned())
                 .unwrap_or_else(|| read_input("Password? (hidden) ".to_owned()));
 
-            get_access_token(
-                url,
-                login_redirect_url,
-                client_id,
-                authorization_server_id,
-                username,
-                password,
-                scopes,
-                copy_to_clipboard,
-                print_token_json,
-            )
+            secret_store
+                .set(&username_secret_name(&url), &username)
+                .and_then(|_| secret_store.set(&password_secret_name(&url, &username), &password))
+                .unwrap_or_else(|e| show_error(e));
+
+            eprintln!(
+                "✅  {} {}",
+                "Credentials Saved For".green().bold(),
+                username.underline()
+            );
         }
-        _ => {
-            println!("Run with --help for usage.")
+        Some(("clear", args)) => {
+            let url = args.value_of("base-url").unwrap().to_owned();
+
+            let username = args
+                .value_of("username")
+                .map(|s| s.to_owned())
+                .or_else(|| get_secret(Some(&secret_store), &username_secret_name(&url)));
+
+            if let Some(username) = &username {
+                secret_store
+                    .delete(&password_secret_name(&url, username))
+                    .unwrap_or_else(|e| show_error(e));
+            }
+
+            secret_store
+                .delete(&username_secret_name(&url))
+                .unwrap_or_else(|e| show_error(e));
+
+            eprintln!("✅  {}", "Credentials Cleared".green().bold());
         }
+        _ => unreachable!(),
     }
 }
 
-fn get_access_token(
-    url: String,
-    login_redirect_url: String,
-    client_id: String,
-    authorization_server_id: String,
-    username: String,
-    password: String,
-    scopes: String,
-    copy_to_clipboard: bool,
-    print_token_json: bool,
-) -> () {
-    eprintln!(
-        "🔐 Getting Access Token for {}",
-        username.to_owned().underline()
-    );
+/// Flags to identify the OKTA tenant and app, which can also be read from a profile
+fn tenant_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("profile")
+            .long("profile")
+            .value_name("profile")
+            .help("Read flags from this profile in the config file, flags that are given override the profile")
+            .required(false),
+        Arg::new("config")
+            .long("config")
+            .value_name("config")
+            .requires("profile")
+            .help("Path to the config file (optional, defaults to ~/.config/auth-token/config.toml)")
+            .required(false),
+        Arg::new("base-url")
+            .long("base-url")
+            .value_name("base-url")
+            .help("Base URL of the OKTA Tenant (ie. https://myapp.okta.com/)")
+            .required(false),
+        Arg::new("client-id")
+            .value_name("client-id")
+            .long("client-id")
+            .help("The OKTA Client ID associated with the app")
+            .required(false),
+        Arg::new("authorization-server-id")
+            .value_name("authorization-server-id")
+            .long("authorization-server-id")
+            .help("If using a custom Authorization Server, the ID for that authorization server")
+            .default_value("default"),
+        Arg::new("issuer")
+            .value_name("issuer")
+            .long("issuer")
+            .help("The issuer URL of the authorization server, instead of --authorization-server-id (ie. https://myapp.okta.com for the org authorization server)")
+            .conflicts_with("authorization-server-id")
+            .required(false),
+    ]
+}
 
-    let client = OktaClient::new(
-        username.to_owned(),
-        password.to_owned(),
-        client_id.to_owned(),
-        authorization_server_id.to_owned(),
-        login_redirect_url.to_owned(),
-        url.to_owned(),
-        scopes.to_owned(),
-    );
+/// A client builder for the tenant and app given by the flags or profile
+fn tenant_builder(options: &Options) -> OktaClientBuilder {
+    let client_id = options
+        .required("client-id")
+        .unwrap_or_else(|e| show_error(e));
+    let mut builder = OktaClient::builder().client_id(client_id);
+
+    builder = match options.issuer() {
+        Some(issuer) => builder.issuer(issuer),
+        None => builder.authorization_server_id(
+            options
+                .required("authorization-server-id")
+                .unwrap_or_else(|e| show_error(e)),
+        ),
+    };
+
+    builder.base_url(base_url(options).unwrap_or_else(|e| show_error(e)))
+}
+
+/// The base URL from the flags or profile, otherwise the origin of the issuer
+fn base_url(options: &Options) -> Result<String, OktaClientError> {
+    match (options.value_of("base-url"), options.issuer()) {
+        (Some(base_url), _) => Ok(base_url),
+        (None, Some(issuer)) => reqwest::Url::parse(&issuer)
+            .map(|issuer| issuer.origin().ascii_serialization())
+            .map_err(|e| OktaClientError::InvalidUrl(format!("{}: {}", issuer, e))),
+        (None, None) => Err(OktaClientError::Configuration(
+            "--base-url or --issuer is required".to_owned(),
+        )),
+    }
+}
+
+/// Flags to authenticate a confidential app with a client secret or private_key_jwt
+fn client_authentication_args(client_secret_help: &'static str) -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("client-secret")
+            .long("client-secret")
+            .value_name("client-secret")
+            .help(client_secret_help)
+            .required(false),
+        Arg::new("private-key")
+            .long("private-key")
+            .value_name("private-key")
+            .conflicts_with("client-secret")
+            .help("Path to a PEM encoded RSA private key, to authenticate with private_key_jwt instead of a client secret")
+            .required(false),
+        Arg::new("key-id")
+            .long("key-id")
+            .value_name("key-id")
+            .requires("private-key")
+            .help("The key ID (kid) of the private key registered with OKTA")
+            .required(false),
+    ]
+}
+
+/// A client builder for the tenant and app, authenticating with the private key or client secret
+/// if one was given
+fn confidential_builder(options: &Options) -> OktaClientBuilder {
+    let builder = tenant_builder(options);
+
+    match client_authentication(options) {
+        Some(client_authentication) => builder.client_authentication(client_authentication),
+        None => builder,
+    }
+}
+
+/// The private key or client secret from the flags, None for a public app
+fn client_authentication(options: &Options) -> Option<ClientAuthentication> {
+    match (
+        options.value_of("private-key"),
+        options.value_of("client-secret"),
+    ) {
+        (Some(path), _) => Some(ClientAuthentication::PrivateKeyJwt {
+            private_key_pem: std::fs::read_to_string(&path).unwrap_or_else(|e| {
+                show_error(OktaClientError::Configuration(format!(
+                    "Could not read private key {}: {}",
+                    path, e
+                )))
+            }),
+            key_id: options.value_of("key-id"),
+        }),
+        (None, Some(client_secret)) => Some(ClientAuthentication::ClientSecret(client_secret)),
+        (None, None) => None,
+    }
+}
+
+fn credentials_base_url_arg() -> Arg<'static> {
+    Arg::new("base-url")
+        .long("base-url")
+        .value_name("base-url")
+        .help("Base URL of the OKTA Tenant (ie. https://myapp.okta.com/)")
+        .required(true)
+}
+
+/// Flags to control what is printed
+fn output_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("copy-to-clipboard")
+            .long("copy-to-clipboard")
+            .value_name("copy-to-clipboard")
+            .takes_value(false)
+            .help("Copy the result to the system clipboard"),
+        Arg::new("print-token-json")
+            .long("print-token-json")
+            .value_name("print-token-json")
+            .takes_value(false)
+            .help("Print the JSON of the token to stdout instead of the token itself"),
+        Arg::new("print-refresh-token")
+            .long("print-refresh-token")
+            .value_name("print-refresh-token")
+            .takes_value(false)
+            .conflicts_with("print-token-json")
+            .help("Print the refresh token to stdout instead of the access token (requires the offline_access scope)"),
+        Arg::new("verify")
+            .long("verify")
+            .value_name("verify")
+            .takes_value(false)
+            .help("Verify the signature and claims of the tokens against the authorization server's keys (JWKS), including cached tokens (the ID token's nonce is always checked while signing in)"),
+        Arg::new("audience")
+            .long("audience")
+            .value_name("audience")
+            .requires("verify")
+            .help("The audience the access token must be for, ie. api://default (optional, not checked if omitted)")
+            .required(false),
+    ]
+}
+
+/// Verify the tokens if --verify was passed, exiting if they aren't valid
+/// The nonce isn't checked again here, it was checked against the one sent while signing in
+async fn verify_tokens(client: &OktaClient, tokens: &TokenResponse, options: &Options<'_>) {
+    if !options.is_present("verify") {
+        return;
+    }
+
+    if !tokens.access_token.is_empty() {
+        client
+            .verify_access_token(
+                &tokens.access_token,
+                options.value_of("audience").as_deref(),
+            )
+            .await
+            .unwrap_or_else(|e| show_error(e));
+        eprintln!("✅ Access Token verified");
+    }
+
+    if let Some(id_token) = &tokens.id_token {
+        client
+            .verify_id_token(id_token, None)
+            .await
+            .unwrap_or_else(|e| show_error(e));
+        eprintln!("✅ ID Token verified");
+    }
+}
+
+fn output_tokens(tokens: &TokenResponse, options: &Options) {
+    let copy_to_clipboard = options.is_present("copy-to-clipboard");
+    let print_token_json = options.is_present("print-token-json");
+    let print_refresh_token = options.is_present("print-refresh-token");
+
+    if print_refresh_token {
+        match &tokens.refresh_token {
+            Some(refresh_token) => output(refresh_token, "Refresh Token", copy_to_clipboard),
+            None => show_error(OktaClientError::General(
+                "No refresh token was returned, was the offline_access scope requested?".to_owned(),
+            )),
+        }
+    } else {
+        // Only an ID token is returned for --response-type id_token
+        let (token, label) = match &tokens.id_token {
+            Some(id_token) if tokens.access_token.is_empty() => (id_token, "ID Token"),
+            _ => (&tokens.access_token, "Access Token"),
+        };
 
-    match client {
-        Ok(client) => {
-            let token = client.get_access_token().unwrap_or_else(|e| show_error(e));
-
-            if !print_token_json {
-                // Print token to stdout
-                println!("{}", token);
-
-                if copy_to_clipboard {
-                    eprintln!(
-                        "✅  {}",
-                        "Access Token Copied To Clipboard\n".green().bold()
-                    );
-                    let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-                    ctx.set_contents(token.to_owned()).unwrap();
+        if !print_token_json {
+            output(token, label, copy_to_clipboard)
+        } else if let Some(token_section) = token.split('.').nth(1) {
+            // Parse token
+            if let Ok(decoded_token_section) = base64::decode(token_section) {
+                if let Ok(decoded_token_section_string) =
+                    std::str::from_utf8(&decoded_token_section)
+                {
+                    output(
+                        decoded_token_section_string,
+                        &format!("{} JSON", label),
+                        copy_to_clipboard,
+                    )
                 }
-            } else {
-                // Parse token
-                if let Some(token_section) = token.split(".").nth(1) {
-                    if let Ok(decoded_token_section) = base64::decode(token_section) {
-                        if let Ok(decoded_token_section_string) =
-                            std::str::from_utf8(&decoded_token_section)
-                        {
-                            println!("{}", decoded_token_section_string);
-
-                            if copy_to_clipboard {
-                                eprintln!(
-                                    "✅  {}",
-                                    "Access Token JSON Copied To Clipboard\n".green().bold()
-                                );
-                                let mut ctx: ClipboardContext = ClipboardProvider::new().unwrap();
-                                ctx.set_contents(decoded_token_section_string.to_owned())
-                                    .unwrap();
-                            }
-                        }
-                    }
+            }
+        }
+    }
+}
+
+/// Print a value to stdout, and optionally copy it to the clipboard
+fn output(value: &str, label: &str, copy_to_clipboard: bool) {
+    println!("{}", value);
+
+    if copy_to_clipboard {
+        let copied = ClipboardProvider::new()
+            .and_then(|mut ctx: ClipboardContext| ctx.set_contents(value.to_owned()));
+
+        match copied {
+            Ok(()) => eprintln!(
+                "✅  {}",
+                format!("{} Copied To Clipboard\n", label).green().bold()
+            ),
+            Err(e) => show_warning(&OktaClientError::General(format!(
+                "Could not copy to clipboard: {}",
+                e
+            ))),
+        }
+    }
+}
+
+/// Reads MFA passcodes from flags, otherwise from the CLI
+struct CliMfaPrompt {
+    totp_code: Option<String>,
+}
+
+impl MfaPrompt for CliMfaPrompt {
+    fn passcode(&self, factor: &OktaFactor) -> Result<String, OktaClientError> {
+        eprintln!(
+            "📱 MFA is required, verifying with {}",
+            factor.description()
+        );
+
+        Ok(self
+            .totp_code
+            .to_owned()
+            .unwrap_or_else(|| read_input(format!("{} Code? (hidden) ", factor.description()))))
+    }
+
+    fn challenge(&self, factor: &OktaFactor) -> Result<ChallengeAnswer, OktaClientError> {
+        eprintln!(
+            "📱 MFA is required, sent a code with {}",
+            factor.description()
+        );
+
+        let code = read_input(format!(
+            "{} Code? (hidden, or r to resend) ",
+            factor.description()
+        ));
+
+        Ok(match code.trim() {
+            "r" => ChallengeAnswer::Resend,
+            code => ChallengeAnswer::Passcode(code.to_owned()),
+        })
+    }
+
+    fn push_status(&self, factor: &OktaFactor, status: &PushStatus) {
+        match status {
+            PushStatus::Sent { correct_answer } => {
+                eprintln!(
+                    "📱 MFA is required, sent a push to {}",
+                    factor.description()
+                );
+                if let Some(answer) = correct_answer {
+                    eprintln!("🔢 Tap {} in Okta Verify", answer.to_string().bold());
                 }
+                eprint!("⏳ Waiting for approval");
             }
+            PushStatus::Waiting => eprint!("."),
+            PushStatus::Approved => eprintln!("\n✅ Push approved"),
+            PushStatus::Rejected => eprintln!("\n😔 Push rejected"),
+            PushStatus::TimedOut => eprintln!("\n😔 Push timed out"),
         }
-        Err(e) => {
-            show_error(e);
+    }
+}
+
+/// Shows the authorize URL too, in case the browser can't be opened, ie. over SSH
+struct CliBrowser;
+
+impl Browser for CliBrowser {
+    fn open(&self, url: &str) -> Result<(), OktaClientError> {
+        eprintln!("🌐 If your browser doesn't open, visit:\n{}", url);
+
+        if let Err(e) = SystemBrowser.open(url) {
+            show_warning(&e);
         }
+
+        Ok(())
     }
 }
 
+struct CliPasswordPrompt;
+
+impl PasswordPrompt for CliPasswordPrompt {
+    fn new_password(&self) -> Result<String, OktaClientError> {
+        eprintln!("🔑 Your password has expired, choose a new one");
+
+        let new_password = read_input("New Password? (hidden) ".to_owned());
+        let confirmation = read_input("Confirm New Password? (hidden) ".to_owned());
+
+        if new_password.is_empty() {
+            return Err(OktaClientError::PasswordExpired(
+                "No new password was entered".to_owned(),
+            ));
+        }
+
+        if new_password != confirmation {
+            return Err(OktaClientError::PasswordExpired(
+                "The new passwords didn't match".to_owned(),
+            ));
+        }
+
+        Ok(new_password)
+    }
+
+    fn password_warning(&self, expires_in_days: Option<u32>) {
+        match expires_in_days {
+            Some(days) => eprintln!(
+                "⚠️  {} Your password expires in {} day(s), change it soon",
+                "Warning:".yellow().bold(),
+                days
+            ),
+            None => eprintln!(
+                "⚠️  {} Your password expires soon, change it from the OKTA sign in page",
+                "Warning:".yellow().bold()
+            ),
+        }
+    }
+}
+
+fn options(args: &ArgMatches) -> Options<'_> {
+    Options::new(args).unwrap_or_else(|e| show_error(e))
+}
+
+/// Open the secret store, warning instead of failing if it can't be used
+fn secret_store() -> Option<Arc<dyn SecretStore>> {
+    default_secret_store().map_err(|e| show_warning(&e)).ok()
+}
+
+/// The secret store, only opened when it is first used, so runs that don't need it don't probe the keyring
+#[derive(Default)]
+struct LazySecretStore(RefCell<Option<Option<Arc<dyn SecretStore>>>>);
+
+impl LazySecretStore {
+    fn open(&self) -> Option<Arc<dyn SecretStore>> {
+        self.0
+            .borrow_mut()
+            .get_or_insert_with(secret_store)
+            .to_owned()
+    }
+}
+
+/// The token cache in the user's cache directory, keeping refresh tokens in the secret store if it can be used
+fn token_cache(secret_store: Option<&Arc<dyn SecretStore>>) -> Option<TokenCache> {
+    TokenCache::default_path().map(|path| match secret_store {
+        Some(secret_store) => TokenCache::new(path).with_secret_store(secret_store.to_owned()),
+        None => TokenCache::new(path),
+    })
+}
+
+/// Read a saved secret, warning instead of failing if it can't be read
+fn get_secret(secret_store: Option<&Arc<dyn SecretStore>>, name: &str) -> Option<String> {
+    secret_store?
+        .get(name)
+        .map_err(|e| show_warning(&e))
+        .ok()
+        .flatten()
+}
+
 fn read_input(message: String) -> String {
     rpassword::prompt_password_stderr(&message).unwrap_or("".to_owned())
 }
 
-fn show_error(error: OktaClientError) -> String {
+fn show_warning(error: &OktaClientError) {
+    eprintln!("⚠️  {} {}", "Warning:".yellow().bold(), error);
+}
+
+fn show_error(error: OktaClientError) -> ! {
     eprintln!("😔 {} {}", "Error:".red().bold(), error);
+    if let Some(hint) = error_hint(&error) {
+        eprintln!("💡 {}", hint);
+    }
     std::process::exit(1);
 }
+
+/// What the user can do about an error that can't be fixed with flags
+fn error_hint(error: &OktaClientError) -> Option<&'static str> {
+    match error {
+        OktaClientError::LockedOut(_) => Some("Unlock your account from the OKTA sign in page, or ask an administrator to unlock it"),
+        OktaClientError::PasswordExpired(_) => Some("Change your password from the OKTA sign in page, then update it with auth-token credentials set if it is saved"),
+        OktaClientError::PasswordReset(_) => Some("Reset your password with \"Forgot password?\" on the OKTA sign in page"),
+        OktaClientError::MfaEnroll(_) => Some("Sign in to OKTA in a browser to set up MFA, then try again"),
+        OktaClientError::Unauthenticated(_) => Some("The sign in took too long, try again"),
+        OktaClientError::StateMismatch(_) | OktaClientError::NonceMismatch(_) => Some("Try again, if it keeps happening the sign in may have been tampered with"),
+        OktaClientError::DeviceAuthorization(_) => Some("Run okta-device-login again to get a new code"),
+        OktaClientError::AuthnStatus(_) | OktaClientError::Idx(_) => Some("Sign in to OKTA in a browser to finish signing in, then try again"),
+        _ => None,
+    }
+}
@@ -1,70 +1,210 @@
+pub mod blocking;
+
 mod authn;
 mod authorize;
+mod browser;
+mod builder;
+mod cache;
+mod client_auth;
+mod device;
 mod error;
+mod flow;
+mod idx;
+mod introspect;
+mod jwks;
+mod mfa;
 mod openid;
+mod password;
 mod pkce;
+mod revoke;
+mod secret_store;
 mod token;
+mod totp;
 
+pub use authn::AuthnStatus;
+pub use authorize::{AuthorizeResponse, ResponseMode, ResponseType};
+pub use browser::{Browser, SystemBrowser};
+pub use builder::OktaClientBuilder;
+pub use cache::{CachedTokens, TokenCache, TokenCacheKey};
+pub use client_auth::ClientAuthentication;
+pub use device::DeviceAuthorization;
 pub use error::OktaClientError;
+pub use flow::Flow;
+pub use introspect::TokenIntrospection;
+pub use jwks::Claims;
+pub use mfa::{ChallengeAnswer, FactorType, MfaPrompt, OktaFactor, Passcode, PushStatus};
+pub use openid::OpenIDConfig;
+pub use password::PasswordPrompt;
+pub use revoke::TokenTypeHint;
+#[cfg(feature = "keyring")]
+pub use secret_store::KeyringStore;
+pub use secret_store::{
+    default_secret_store, password_secret_name, username_secret_name, EncryptedFileStore,
+    SecretStore,
+};
+pub use token::TokenResponse;
+pub use totp::Totp;
 
 pub struct OktaClient {
     client_id: String,
-    base_url: String,
-    login_redirect_url: String,
-    username: String,
-    password: String,
-    authorization_endpoint: String,
-    token_endpoint: String,
-    pkce: pkce::PKCE,
+    base_url: reqwest::Url,
+    authorization_server_id: String,
+    login_redirect_url: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    openid_config: OpenIDConfig,
+    jwks: jwks::KeyCache,
+    pkce: pkce::Pkce,
     scopes: String,
+    client_authentication: ClientAuthentication,
+    flow: Flow,
+    response_type: ResponseType,
+    response_mode: ResponseMode,
+    factor: Option<FactorType>,
+    totp: Option<Totp>,
+    http: reqwest::Client,
+    mfa_prompt: Option<Box<dyn MfaPrompt>>,
+    password_prompt: Option<Box<dyn PasswordPrompt>>,
+    browser: Box<dyn Browser>,
 }
 
 impl OktaClient {
-    /// Create a new OKTA Client
-    pub fn new(
-        username: String,
-        password: String,
-        client_id: String,
-        authorization_server_id: String,
-        login_redirect_url: String,
-        base_url: String,
-        scopes: String,
-    ) -> Result<Self, OktaClientError> {
-        let openid_config =
-            openid::get_openid_config(base_url.to_owned(), authorization_server_id.to_owned())?;
-
-        Ok(OktaClient {
-            username,
-            password,
-            client_id,
-            login_redirect_url,
-            base_url,
-            authorization_endpoint: openid_config.authorization_endpoint,
-            token_endpoint: openid_config.token_endpoint,
-            pkce: pkce::PKCE::new(),
-            scopes,
-        })
+    /// Configure a new OKTA Client
+    pub fn builder() -> OktaClientBuilder {
+        OktaClientBuilder::new()
     }
 
     /// Get an access token for a specific OKTA tenant and client/app
-    #[tokio::main]
     pub async fn get_access_token(&self) -> Result<String, OktaClientError> {
-        // Get Session token from /authn
+        Ok(self.get_tokens().await?.access_token)
+    }
+
+    /// Get the access, ID and refresh tokens for a specific OKTA tenant and client/app
+    pub async fn get_tokens(&self) -> Result<TokenResponse, OktaClientError> {
+        match self.flow {
+            Flow::Authn => self.get_tokens_with_authn().await,
+            Flow::Password => self.do_oauth_password().await,
+            Flow::Idx => self.get_tokens_with_idx().await,
+            Flow::Browser => self.get_tokens_with_browser().await,
+        }
+    }
+
+    /// Get the tokens from the cache while the access token is valid, otherwise refresh
+    /// them with the cached refresh token, or sign in again and cache the new tokens
+    pub async fn get_tokens_with_cache(
+        &self,
+        cache: &TokenCache,
+    ) -> Result<TokenResponse, OktaClientError> {
+        if let Some(tokens) = self.get_cached_tokens(cache).await? {
+            return Ok(tokens);
+        }
+
+        let tokens = self.get_tokens().await?;
+        cache.put(&self.cache_key(), &tokens)?;
+
+        Ok(tokens)
+    }
+
+    /// Get the tokens from the cache while the access token is valid, otherwise refresh them
+    /// with the cached refresh token, None if the user must sign in again
+    /// Doesn't need the user's password, so it can be tried before asking for it
+    pub async fn get_cached_tokens(
+        &self,
+        cache: &TokenCache,
+    ) -> Result<Option<TokenResponse>, OktaClientError> {
+        let key = self.cache_key();
+
+        let cached = match cache.get(&key)? {
+            Some(cached) => cached,
+            None => return Ok(None),
+        };
+
+        if !cached.is_expired() {
+            return Ok(Some(cached.tokens));
+        }
+
+        if let Some(refresh_token) = cached.tokens.refresh_token {
+            // If the refresh token has also expired or been revoked, sign in again
+            if let Ok(mut tokens) = self.do_oauth_refresh_token(refresh_token.to_owned()).await {
+                // Keep the refresh token unless it was rotated
+                tokens.refresh_token.get_or_insert(refresh_token);
+                cache.put(&key, &tokens)?;
+                return Ok(Some(tokens));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The OpenID discovery document of the authorization server
+    pub fn openid_config(&self) -> &OpenIDConfig {
+        &self.openid_config
+    }
+
+    /// The key this client's tokens are cached under
+    pub fn cache_key(&self) -> TokenCacheKey {
+        TokenCacheKey::new(
+            self.base_url.to_string(),
+            self.authorization_server_id.to_owned(),
+            self.client_id.to_owned(),
+            self.username.to_owned().unwrap_or_default(),
+            self.scopes.to_owned(),
+        )
+    }
+
+    async fn get_tokens_with_authn(&self) -> Result<TokenResponse, OktaClientError> {
+        // Get Session token from /authn, verifying a factor or changing the password if required
         let okta_session = self.do_okta_authn().await?;
+        let session_token = self.do_okta_authn_transaction(okta_session).await?;
 
-        // Get Auth Code from /authorization
-        let auth_code = self
-            .do_oauth_authorize(
-                okta_session
-                    .session_token
-                    .expect("Missing Session Token")
-                    .to_owned(),
-            )
-            .await?;
+        // Get Auth Code (or the tokens, for an implicit response type) from /authorization
+        let (auth_code, nonce) = match self.do_oauth_authorize(session_token).await? {
+            AuthorizeResponse::Code { code, nonce } => (code, nonce),
+            AuthorizeResponse::Tokens(tokens) => return Ok(tokens),
+        };
 
-        // Get Access Token from /token
-        let token = self.do_oauth_token(auth_code.to_owned()).await?;
+        // Get Tokens from /token
+        let tokens = self.do_oauth_token(auth_code.to_owned()).await?;
+        authorize::verify_nonce(&tokens, &nonce)?;
+
+        Ok(tokens)
+    }
+
+    /// Exchange a refresh token for a new set of tokens
+    /// The offline_access scope must have been requested to receive a refresh token
+    pub async fn refresh(&self, refresh_token: String) -> Result<TokenResponse, OktaClientError> {
+        self.openid_config.validate_grant_type("refresh_token")?;
+        self.do_oauth_refresh_token(refresh_token).await
+    }
+
+    /// Get an access token for the client/app itself, for service-to-service calls
+    /// Requires client authentication and a custom authorization server
+    pub async fn client_credentials(&self) -> Result<TokenResponse, OktaClientError> {
+        self.openid_config
+            .validate_grant_type("client_credentials")?;
+        self.do_oauth_client_credentials().await
+    }
+
+    /// A URL on the OKTA tenant, ie. api/v1/authn
+    fn okta_url(&self, path: &str) -> String {
+        match self.base_url.join(path) {
+            Ok(url) => url.into(),
+            Err(_) => format!("{}{}", self.base_url, path),
+        }
+    }
+
+    fn login_redirect_url(&self) -> Result<&str, OktaClientError> {
+        self.login_redirect_url.as_deref().ok_or_else(|| {
+            OktaClientError::Configuration("A login redirect URL is required".to_owned())
+        })
+    }
 
-        Ok(token)
+    fn credentials(&self) -> Result<(&str, &str), OktaClientError> {
+        match (&self.username, &self.password) {
+            (Some(username), Some(password)) => Ok((username, password)),
+            _ => Err(OktaClientError::Configuration(
+                "A username and password are required".to_owned(),
+            )),
+        }
     }
 }
@@ -1,4 +1,5 @@
 /// Call the OKTA /api/v1/authn endpoint
+use crate::okta::mfa::OktaFactor;
 use crate::okta::{OktaClient, OktaClientError};
 
 use serde::{Deserialize, Serialize};
@@ -10,57 +11,221 @@ pub struct OktaAuthnRequest {
     pub password: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The state of an /api/v1/authn transaction
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AuthnStatus {
+    Unauthenticated,
+    PasswordWarn,
+    PasswordExpired,
+    Recovery,
+    RecoveryChallenge,
+    PasswordReset,
+    LockedOut,
+    MfaEnroll,
+    MfaEnrollActivate,
+    MfaRequired,
+    MfaChallenge,
+    Success,
+    /// A status added to OKTA after this was written
+    #[serde(other)]
+    Unknown,
+}
+
+impl std::fmt::Display for AuthnStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            AuthnStatus::Unauthenticated => "UNAUTHENTICATED",
+            AuthnStatus::PasswordWarn => "PASSWORD_WARN",
+            AuthnStatus::PasswordExpired => "PASSWORD_EXPIRED",
+            AuthnStatus::Recovery => "RECOVERY",
+            AuthnStatus::RecoveryChallenge => "RECOVERY_CHALLENGE",
+            AuthnStatus::PasswordReset => "PASSWORD_RESET",
+            AuthnStatus::LockedOut => "LOCKED_OUT",
+            AuthnStatus::MfaEnroll => "MFA_ENROLL",
+            AuthnStatus::MfaEnrollActivate => "MFA_ENROLL_ACTIVATE",
+            AuthnStatus::MfaRequired => "MFA_REQUIRED",
+            AuthnStatus::MfaChallenge => "MFA_CHALLENGE",
+            AuthnStatus::Success => "SUCCESS",
+            AuthnStatus::Unknown => "UNKNOWN",
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase", default)]
 pub struct OktaAuthnResponse {
     pub expires_at: Option<String>,
-    pub status: Option<String>,
+    pub status: Option<AuthnStatus>,
     pub session_token: Option<String>,
+    pub state_token: Option<String>,
+    /// The state of a factor challenge, ie. WAITING, REJECTED or TIMEOUT
+    pub factor_result: Option<String>,
     pub error_code: Option<String>,
     pub error_summary: Option<String>,
     pub error_id: Option<String>,
+    #[serde(rename = "_embedded")]
+    pub embedded: OktaAuthnEmbedded,
+    #[serde(rename = "_links")]
+    pub links: OktaAuthnLinks,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OktaAuthnEmbedded {
+    /// The factors the user has enrolled, when the status is MFA_REQUIRED
+    pub factors: Vec<OktaFactor>,
+    /// The factor being verified, when the status is MFA_CHALLENGE
+    pub factor: Option<OktaFactor>,
+    /// The password policy, when the status is PASSWORD_WARN or PASSWORD_EXPIRED
+    pub policy: Option<OktaPasswordPolicy>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OktaPasswordPolicy {
+    pub expiration: OktaPasswordExpiration,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OktaPasswordExpiration {
+    pub password_expire_days: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct OktaAuthnLinks {
+    /// Where to poll or verify next, when the status is MFA_CHALLENGE
+    pub next: Option<OktaLink>,
+    /// Where to send another code, for SMS, call and email factors
+    pub resend: Vec<OktaLink>,
+    /// Where to continue without changing the password, when the status is PASSWORD_WARN
+    pub skip: Option<OktaLink>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct OktaLink {
+    pub href: String,
 }
 
 impl OktaAuthnResponse {
     fn as_error(&self) -> Option<OktaClientError> {
-        match self.error_code {
-            None => None,
-            _ => Some(OktaClientError::OktaAPI(
-                self.error_summary.as_ref().unwrap().to_owned(),
-            )),
-        }
+        self.error_code.as_ref().map(|error_code| {
+            OktaClientError::OktaAPI(
+                self.error_summary
+                    .to_owned()
+                    .unwrap_or_else(|| error_code.to_owned()),
+            )
+        })
     }
 }
 
-impl Default for OktaAuthnResponse {
-    fn default() -> Self {
-        OktaAuthnResponse {
-            expires_at: None,
-            status: None,
-            session_token: None,
-            error_code: None,
-            error_summary: None,
-            error_id: None,
-        }
-    }
+/// Continue an authn transaction without changing anything, ie. to skip PASSWORD_WARN
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OktaStateTokenRequest {
+    state_token: String,
 }
 
 impl OktaClient {
+    /// Follow an authn transaction from its first status until it has a session token
+    pub async fn do_okta_authn_transaction(
+        &self,
+        mut transaction: OktaAuthnResponse,
+    ) -> Result<String, OktaClientError> {
+        loop {
+            transaction = match transaction.status {
+                Some(AuthnStatus::Success) => {
+                    return transaction.session_token.ok_or_else(|| {
+                        OktaClientError::MissingSessionToken("status SUCCESS".to_owned())
+                    })
+                }
+                Some(AuthnStatus::MfaRequired) => self.do_okta_mfa(transaction).await?,
+                Some(AuthnStatus::PasswordExpired) => {
+                    self.do_okta_change_password(transaction).await?
+                }
+                Some(AuthnStatus::PasswordWarn) => self.skip_password_warning(transaction).await?,
+                Some(AuthnStatus::LockedOut) => {
+                    return Err(OktaClientError::LockedOut(
+                        "Too many failed sign in attempts".to_owned(),
+                    ))
+                }
+                Some(AuthnStatus::PasswordReset) | Some(AuthnStatus::Recovery) => {
+                    return Err(OktaClientError::PasswordReset(
+                        "The password must be reset before signing in".to_owned(),
+                    ))
+                }
+                Some(AuthnStatus::MfaEnroll) | Some(AuthnStatus::MfaEnrollActivate) => {
+                    return Err(OktaClientError::MfaEnroll(
+                        "A factor must be enrolled before signing in".to_owned(),
+                    ))
+                }
+                Some(AuthnStatus::Unauthenticated) => {
+                    return Err(OktaClientError::Unauthenticated(
+                        "The transaction has no user".to_owned(),
+                    ))
+                }
+                Some(AuthnStatus::Unknown) => {
+                    return Err(OktaClientError::AuthnStatus(
+                        "OKTA returned a status that isn't recognised".to_owned(),
+                    ))
+                }
+                Some(status) => return Err(OktaClientError::AuthnStatus(status.to_string())),
+                None => return Err(OktaClientError::MissingSessionToken("no status".to_owned())),
+            }
+        }
+    }
+
+    async fn skip_password_warning(
+        &self,
+        transaction: OktaAuthnResponse,
+    ) -> Result<OktaAuthnResponse, OktaClientError> {
+        if let Some(prompt) = &self.password_prompt {
+            prompt.password_warning(
+                transaction
+                    .embedded
+                    .policy
+                    .as_ref()
+                    .and_then(|policy| policy.expiration.password_expire_days),
+            );
+        }
+
+        let state_token = transaction.state_token.ok_or_else(|| {
+            OktaClientError::Parser("PASSWORD_WARN response was missing stateToken".to_owned())
+        })?;
+
+        let url = match transaction.links.skip {
+            Some(skip) => skip.href,
+            None => self.okta_url("api/v1/authn/skip"),
+        };
+
+        self.post_authn(&url, &OktaStateTokenRequest { state_token })
+            .await
+    }
+
     /// Use a username and password to get a session token
     pub async fn do_okta_authn(&self) -> Result<OktaAuthnResponse, OktaClientError> {
+        let (username, password) = self.credentials()?;
+
         let request = OktaAuthnRequest {
-            username: self.username.to_owned(),
-            password: self.password.to_owned(),
+            username: username.to_owned(),
+            password: password.to_owned(),
         };
 
-        let client = reqwest::Client::new();
-
         // Post to /authn
-        let req = client
-            .post(format!("{}/api/v1/authn", self.base_url))
-            .json(&request)
-            .send()
-            .await?;
+        self.post_authn(&self.okta_url("api/v1/authn"), &request)
+            .await
+    }
+
+    /// Post to an /authn endpoint, ie. to verify a factor
+    pub(crate) async fn post_authn(
+        &self,
+        url: &str,
+        request: &impl Serialize,
+    ) -> Result<OktaAuthnResponse, OktaClientError> {
+        let req = self.http.post(url).json(request).send().await?;
 
         // Deserialize
         let response = req.json::<OktaAuthnResponse>().await?;
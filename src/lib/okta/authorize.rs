@@ -1,9 +1,17 @@
 /// Call the OAuth authorize endpoint
-use crate::okta::{OktaClient, OktaClientError};
+use crate::okta::{OktaClient, OktaClientError, TokenResponse};
 
 use rand::Rng;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::string::String;
 
+/// Stop following redirects from OKTA's own pages after this many
+const MAX_REDIRECTS: usize = 10;
+
+/// Length of the state and nonce, 32 alphanumeric characters is about 190 bits
+const RANDOM_STRING_LENGTH: usize = 32;
+
 #[derive(Debug)]
 pub enum CodeChallengeMethod {
     //Plain,      // Not Implemented
@@ -19,44 +27,88 @@ impl std::fmt::Display for CodeChallengeMethod {
     }
 }
 
-#[derive(Debug)]
+/// What the authorize endpoint returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ResponseType {
+    /// An auth code, exchanged at the token endpoint
+    #[default]
     Code,
-    //Token,      // Not Implemented
-    //IdToken,    // Not Implemented
+    /// An access token, without a refresh token (implicit)
+    Token,
+    /// An ID token only, without an access token (implicit)
+    IdToken,
     //None        // Not Implemented
 }
 
+impl ResponseType {
+    pub const NAMES: [&'static str; 3] = ["code", "token", "id_token"];
+}
+
 impl std::fmt::Display for ResponseType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             ResponseType::Code => f.write_str("code"),
-            //ResponseType::Token => f.write_str("token"),
-            //ResponseType::IdToken => f.write_str("id_token"),
+            ResponseType::Token => f.write_str("token"),
+            ResponseType::IdToken => f.write_str("id_token"),
             //ResponseType::None => f.write_str("none"),
         }
     }
 }
 
-#[derive(Debug)]
+impl std::str::FromStr for ResponseType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "code" => Ok(ResponseType::Code),
+            "token" => Ok(ResponseType::Token),
+            "id_token" => Ok(ResponseType::IdToken),
+            _ => Err(format!("Unknown response type {}", s)),
+        }
+    }
+}
+
+/// How the authorize endpoint sends the response to the login redirect URL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ResponseMode {
-    //Query,      // Not Implemented
-    //Fragment,   // Not Implemented
+    /// In the query string of the redirect, only for response_type: code
+    Query,
+    /// In the fragment of the redirect
+    Fragment,
+    /// In a HTML form that posts to the login redirect URL
+    #[default]
     FormPost,
     //WebMessage  // Not Implemented
 }
 
+impl ResponseMode {
+    pub const NAMES: [&'static str; 3] = ["query", "fragment", "form_post"];
+}
+
 impl std::fmt::Display for ResponseMode {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            //ResponseMode::Query => f.write_str("query"),
-            //ResponseMode::Fragment => f.write_str("fragment"),
+            ResponseMode::Query => f.write_str("query"),
+            ResponseMode::Fragment => f.write_str("fragment"),
             ResponseMode::FormPost => f.write_str("form_post"),
             //ResponseMode::WebMessage => f.write_str("web_message"),
         }
     }
 }
 
+impl std::str::FromStr for ResponseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "query" => Ok(ResponseMode::Query),
+            "fragment" => Ok(ResponseMode::Fragment),
+            "form_post" => Ok(ResponseMode::FormPost),
+            _ => Err(format!("Unknown response mode {}", s)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Prompt {
     None,
@@ -78,16 +130,17 @@ pub struct OktaAuthorizeRequest {
     pub code_challenge: String,
     pub redirect_uri: String,
     pub scope: String,
-    pub prompt: Prompt,
+    /// Omitted when the user signs in interactively, ie. in a browser
+    pub prompt: Option<Prompt>,
     pub response_mode: ResponseMode,
     pub state: String,
     pub nonce: String,
-    pub session_token: String,
+    pub session_token: Option<String>,
 }
 
 impl OktaAuthorizeRequest {
     pub fn as_params(&self) -> Vec<(&str, String)> {
-        vec![
+        let mut params = vec![
             ("client_id", self.client_id.to_owned()),
             ("response_type", self.response_type.to_string().to_owned()),
             (
@@ -97,80 +150,418 @@ impl OktaAuthorizeRequest {
             ("code_challenge", self.code_challenge.to_owned()),
             ("redirect_uri", self.redirect_uri.to_owned()),
             ("scope", self.scope.to_owned()),
-            ("prompt", self.prompt.to_string().to_owned()),
             ("response_mode", self.response_mode.to_string().to_owned()),
             ("state", self.state.to_owned()),
-            ("sessionToken", self.session_token.to_owned()),
             ("nonce", self.nonce.to_owned()),
-        ]
+        ];
+
+        if let Some(prompt) = &self.prompt {
+            params.push(("prompt", prompt.to_string()));
+        }
+
+        if let Some(session_token) = &self.session_token {
+            params.push(("sessionToken", session_token.to_owned()));
+        }
+
+        params
     }
 }
 
+/// What the authorize endpoint sent back to the login redirect URL
+#[derive(Debug)]
+pub enum AuthorizeResponse {
+    /// An auth code to exchange at the token endpoint, for ResponseType::Code
+    /// The nonce is checked against the ID token once the code is exchanged, with verify_nonce
+    Code { code: String, nonce: String },
+    /// The tokens themselves, for ResponseType::Token and ResponseType::IdToken
+    Tokens(TokenResponse),
+}
+
 impl OktaClient {
-    /// Use a Session Token to get an auth code
+    /// Use a Session Token to get an auth code, or the tokens with an implicit response type
     pub async fn do_oauth_authorize(
         &self,
         session_token: String,
-    ) -> Result<String, OktaClientError> {
+    ) -> Result<AuthorizeResponse, OktaClientError> {
+        let redirect_uri = self.login_redirect_url()?;
         let request = OktaAuthorizeRequest {
             client_id: self.client_id.to_owned(),
-            response_type: ResponseType::Code,
+            response_type: self.response_type,
             code_challenge_method: CodeChallengeMethod::S256,
             code_challenge: self.pkce.code_challenge.to_owned(),
-            redirect_uri: self.login_redirect_url.to_owned(),
+            redirect_uri: redirect_uri.to_owned(),
             scope: self.scopes.to_owned(),
-            prompt: Prompt::None,
-            response_mode: ResponseMode::FormPost,
-            state: random_string().to_owned(),
-            nonce: random_string().to_owned(),
-            session_token: session_token.to_owned(),
+            prompt: Some(Prompt::None),
+            response_mode: self.response_mode,
+            state: random_string(),
+            nonce: random_string(),
+            session_token: Some(session_token.to_owned()),
         };
 
-        let url =
-            reqwest::Url::parse_with_params(&self.authorization_endpoint, &request.as_params())
-                .expect("Failed to create URL");
-
-        let client = reqwest::Client::new();
-
-        // Get Text Response to parse HTML for the code response
-        let req = client.get(url).send().await;
-        let text = req.expect("Error getting Code").text().await;
-        let text = text.expect("Invalid Text");
-
-        // Scrape code from <input name='code' value='....' />
-        let dom = scraper::Html::parse_document(&text);
-        let selector = scraper::Selector::parse(r#"input[name="code"]"#).unwrap();
-
-        // Look for the input element named 'code'
-        let element = match dom.select(&selector).next() {
-            Some(element) => element,
-            None => {
-                return Err(OktaClientError::Parser(format!(
-                    "Could not find input element with name code: {}",
-                    text
-                )))
+        let mut url = self.authorize_url(&request)?;
+
+        let params = match request.response_mode {
+            ResponseMode::FormPost => {
+                // Get Text Response to parse HTML for the form posted to the redirect URL
+                let text = self.http.get(url).send().await?.text().await?;
+                form_post_params(&text)?
             }
-        };
+            ResponseMode::Query | ResponseMode::Fragment => {
+                // Follow redirects ourselves until OKTA redirects to the redirect URL
+                let mut redirects = 0;
+                loop {
+                    let response = self.http.get(url.clone()).send().await?;
+                    let location = match redirect_location(&response) {
+                        Some(location) => url.join(location).map_err(|e| {
+                            OktaClientError::InvalidUrl(format!("{}: {}", location, e))
+                        })?,
+                        None => {
+                            return Err(OktaClientError::Parser(format!(
+                                "Expected a redirect to the login redirect URL ({}): {}",
+                                response.status(),
+                                response.text().await?
+                            )))
+                        }
+                    };
+
+                    if location.as_str().starts_with(redirect_uri) {
+                        break redirect_params(&location, request.response_mode);
+                    }
 
-        // Look for the value attribute of that element
-        match element.value().attr("value") {
-            Some(value) => Ok(value.to_owned()),
-            None => {
-                return Err(OktaClientError::Parser(format!(
-                    "Missing value on code: {}",
-                    text
-                )))
+                    redirects += 1;
+                    if redirects >= MAX_REDIRECTS {
+                        return Err(OktaClientError::OktaAPI(
+                            "Too many redirects from the authorize endpoint".to_owned(),
+                        ));
+                    }
+                    url = location;
+                }
             }
+        };
+
+        authorize_response(params, &request)
+    }
+
+    /// The authorize endpoint with the request as query parameters
+    pub fn authorize_url(
+        &self,
+        request: &OktaAuthorizeRequest,
+    ) -> Result<reqwest::Url, OktaClientError> {
+        reqwest::Url::parse_with_params(
+            &self.openid_config.authorization_endpoint,
+            &request.as_params(),
+        )
+        .map_err(|e| {
+            OktaClientError::InvalidUrl(format!(
+                "{}: {}",
+                self.openid_config.authorization_endpoint, e
+            ))
+        })
+    }
+}
+
+/// The hidden inputs of the form OKTA posts to the redirect URL, ie. <input name='code' value='....' />
+fn form_post_params(text: &str) -> Result<HashMap<String, String>, OktaClientError> {
+    let dom = scraper::Html::parse_document(text);
+    let selector = scraper::Selector::parse(r#"form input[name]"#)
+        .map_err(|e| OktaClientError::Parser(format!("Invalid selector: {:?}", e)))?;
+
+    let params = dom
+        .select(&selector)
+        .filter_map(|element| {
+            let name = element.value().attr("name")?;
+            let value = element.value().attr("value").unwrap_or_default();
+            Some((name.to_owned(), value.to_owned()))
+        })
+        .collect::<HashMap<_, _>>();
+
+    // TODO: read divs with class error-code, o-forn-title and o-form-explain for error details
+    if params.is_empty() {
+        return Err(OktaClientError::Parser(format!(
+            "Could not find the form posted to the redirect URL: {}",
+            text
+        )));
+    }
+
+    Ok(params)
+}
+
+fn redirect_location(response: &reqwest::Response) -> Option<&str> {
+    if !response.status().is_redirection() {
+        return None;
+    }
+
+    response
+        .headers()
+        .get(reqwest::header::LOCATION)?
+        .to_str()
+        .ok()
+}
+
+/// The parameters in the query string or fragment of the redirect to the redirect URL
+fn redirect_params(
+    location: &reqwest::Url,
+    response_mode: ResponseMode,
+) -> HashMap<String, String> {
+    match response_mode {
+        ResponseMode::Fragment => {
+            url::form_urlencoded::parse(location.fragment().unwrap_or_default().as_bytes())
+                .into_owned()
+                .collect()
         }
+        _ => location.query_pairs().into_owned().collect(),
+    }
+}
+
+/// The code or tokens from the parameters sent to the redirect URL
+pub(crate) fn authorize_response(
+    mut params: HashMap<String, String>,
+    request: &OktaAuthorizeRequest,
+) -> Result<AuthorizeResponse, OktaClientError> {
+    // Checked first, so a forged error can't be passed off as OKTA's
+    verify_state(params.get("state").map(String::as_str), &request.state)?;
 
-        // TODO: read divs with class error-code, o-forn-title and o-form-explain for error details
+    if let Some(error) = params.remove("error") {
+        return Err(OktaClientError::OktaAPI(format!(
+            "{}: {}",
+            error,
+            params.remove("error_description").unwrap_or_default()
+        )));
     }
+
+    let missing = |name: &str| {
+        OktaClientError::Parser(format!(
+            "{} was missing from the authorize response: {:?}",
+            name,
+            params.keys().collect::<Vec<_>>()
+        ))
+    };
+
+    match request.response_type {
+        ResponseType::Code => match params.get("code") {
+            Some(code) => Ok(AuthorizeResponse::Code {
+                code: code.to_owned(),
+                nonce: request.nonce.to_owned(),
+            }),
+            None => Err(missing("code")),
+        },
+        ResponseType::Token if !params.contains_key("access_token") => Err(missing("access_token")),
+        ResponseType::IdToken if !params.contains_key("id_token") => Err(missing("id_token")),
+        ResponseType::Token | ResponseType::IdToken => {
+            let tokens = TokenResponse {
+                token_type: params.remove("token_type").unwrap_or_default(),
+                access_token: params.remove("access_token").unwrap_or_default(),
+                expires_in: params
+                    .get("expires_in")
+                    .and_then(|expires_in| expires_in.parse().ok())
+                    .unwrap_or_default(),
+                scope: params.remove("scope").unwrap_or_default(),
+                id_token: params.remove("id_token"),
+                refresh_token: None,
+            };
+            verify_nonce(&tokens, &request.nonce)?;
+
+            Ok(AuthorizeResponse::Tokens(tokens))
+        }
+    }
+}
+
+/// The claims of an ID token needed to check the authorize round trip
+#[derive(Deserialize, Debug)]
+struct IdTokenClaims {
+    nonce: Option<String>,
 }
 
-fn random_string() -> String {
+/// Check the state sent back to the redirect URL is the one sent to the authorize endpoint
+pub(crate) fn verify_state(returned: Option<&str>, state: &str) -> Result<(), OktaClientError> {
+    match returned {
+        Some(returned) if returned == state => Ok(()),
+        Some(_) => Err(OktaClientError::StateMismatch(
+            "The state returned by OKTA didn't match the one sent".to_owned(),
+        )),
+        None => Err(OktaClientError::StateMismatch(
+            "OKTA didn't return the state that was sent".to_owned(),
+        )),
+    }
+}
+
+/// Check the ID token, if one was returned, has the nonce sent to the authorize endpoint
+/// The signature isn't verified here, only that the token was issued for this request
+pub(crate) fn verify_nonce(tokens: &TokenResponse, nonce: &str) -> Result<(), OktaClientError> {
+    let id_token = match &tokens.id_token {
+        Some(id_token) => id_token,
+        None => return Ok(()),
+    };
+
+    let claims = id_token
+        .split('.')
+        .nth(1)
+        .and_then(|payload| base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok())
+        .and_then(|payload| serde_json::from_slice::<IdTokenClaims>(&payload).ok())
+        .ok_or_else(|| OktaClientError::Parser("The ID token couldn't be decoded".to_owned()))?;
+
+    match claims.nonce {
+        Some(returned) if returned == nonce => Ok(()),
+        Some(_) => Err(OktaClientError::NonceMismatch(
+            "The nonce in the ID token didn't match the one sent".to_owned(),
+        )),
+        None => Err(OktaClientError::NonceMismatch(
+            "The ID token has no nonce".to_owned(),
+        )),
+    }
+}
+
+/// A random state, nonce or JWT ID, from a cryptographically secure generator
+pub(crate) fn random_string() -> String {
     rand::thread_rng()
         .sample_iter(&rand::distributions::Alphanumeric)
-        .take(8)
+        .take(RANDOM_STRING_LENGTH)
         .map(char::from)
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(response_type: ResponseType) -> OktaAuthorizeRequest {
+        OktaAuthorizeRequest {
+            client_id: "client".to_owned(),
+            response_type,
+            code_challenge_method: CodeChallengeMethod::S256,
+            code_challenge: "challenge".to_owned(),
+            redirect_uri: "http://localhost:8080/callback".to_owned(),
+            scope: "openid".to_owned(),
+            prompt: None,
+            response_mode: ResponseMode::Fragment,
+            state: "state1".to_owned(),
+            nonce: "nonce1".to_owned(),
+            session_token: None,
+        }
+    }
+
+    fn params(params: &[(&str, &str)]) -> HashMap<String, String> {
+        params
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// An unsigned ID token with the given claims, only the payload is read
+    fn id_token(claims: serde_json::Value) -> String {
+        format!(
+            "header.{}.signature",
+            base64::encode_config(claims.to_string(), base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    #[test]
+    fn returns_the_code() {
+        let response = authorize_response(
+            params(&[("code", "code1"), ("state", "state1")]),
+            &request(ResponseType::Code),
+        );
+
+        assert!(matches!(
+            response,
+            Ok(AuthorizeResponse::Code { code, nonce }) if code == "code1" && nonce == "nonce1"
+        ));
+    }
+
+    #[test]
+    fn rejects_a_missing_or_mismatched_state() {
+        for params in [
+            params(&[("code", "code1")]),
+            params(&[("code", "code1"), ("state", "forged")]),
+        ] {
+            assert!(matches!(
+                authorize_response(params, &request(ResponseType::Code)),
+                Err(OktaClientError::StateMismatch(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn checks_the_state_before_an_error() {
+        let error = [
+            ("error", "access_denied"),
+            (
+                "error_description",
+                "User is not assigned to the client application.",
+            ),
+        ];
+
+        assert!(matches!(
+            authorize_response(
+                params(&[error[0], error[1], ("state", "forged")]),
+                &request(ResponseType::Code)
+            ),
+            Err(OktaClientError::StateMismatch(_))
+        ));
+        assert!(matches!(
+            authorize_response(
+                params(&[error[0], error[1], ("state", "state1")]),
+                &request(ResponseType::Code)
+            ),
+            Err(OktaClientError::OktaAPI(e)) if e.starts_with("access_denied: User is not assigned")
+        ));
+    }
+
+    #[test]
+    fn checks_the_nonce_of_returned_tokens() {
+        let response = |id_token: String| {
+            authorize_response(
+                params(&[("id_token", &id_token), ("state", "state1")]),
+                &request(ResponseType::IdToken),
+            )
+        };
+
+        assert!(matches!(
+            response(id_token(serde_json::json!({ "nonce": "nonce1" }))),
+            Ok(AuthorizeResponse::Tokens(tokens)) if tokens.id_token.is_some()
+        ));
+        assert!(matches!(
+            response(id_token(serde_json::json!({ "nonce": "forged" }))),
+            Err(OktaClientError::NonceMismatch(_))
+        ));
+        assert!(matches!(
+            response(id_token(serde_json::json!({ "sub": "user1" }))),
+            Err(OktaClientError::NonceMismatch(_))
+        ));
+        assert!(matches!(
+            response("not a token".to_owned()),
+            Err(OktaClientError::Parser(_))
+        ));
+    }
+
+    #[test]
+    fn requires_the_requested_tokens() {
+        assert!(matches!(
+            authorize_response(
+                params(&[("id_token", "token"), ("state", "state1")]),
+                &request(ResponseType::Token)
+            ),
+            Err(OktaClientError::Parser(_))
+        ));
+    }
+
+    #[test]
+    fn reads_the_fragment_or_query_of_the_redirect() {
+        let location = reqwest::Url::parse(
+            "http://localhost:8080/callback?code=query-code&state=query-state#code=fragment-code&state=fragment%20state",
+        )
+        .unwrap();
+
+        assert_eq!(
+            redirect_params(&location, ResponseMode::Fragment),
+            params(&[("code", "fragment-code"), ("state", "fragment state")])
+        );
+        assert_eq!(
+            redirect_params(&location, ResponseMode::Query),
+            params(&[("code", "query-code"), ("state", "query-state")])
+        );
+
+        let location = reqwest::Url::parse("http://localhost:8080/callback?code=code1").unwrap();
+        assert!(redirect_params(&location, ResponseMode::Fragment).is_empty());
+    }
+}
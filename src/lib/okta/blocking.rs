@@ -0,0 +1,162 @@
+/// A blocking OKTA Client, for callers that don't use an async runtime
+///
+/// Each client runs its own runtime, so it must not be created or used from within an
+/// async runtime. Use `auth_token::okta::OktaClient` there instead.
+use crate::okta::{
+    Claims, DeviceAuthorization, OktaClientBuilder, OktaClientError, TokenCache, TokenCacheKey,
+    TokenIntrospection, TokenResponse, TokenTypeHint,
+};
+
+pub struct OktaClient {
+    inner: crate::okta::OktaClient,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl OktaClient {
+    /// Create a new OKTA Client
+    pub fn new(
+        username: String,
+        password: String,
+        client_id: String,
+        authorization_server_id: String,
+        login_redirect_url: String,
+        base_url: String,
+        scopes: String,
+    ) -> Result<Self, OktaClientError> {
+        OktaClient::build(
+            OktaClientBuilder::new()
+                .credentials(username, password)
+                .client_id(client_id)
+                .authorization_server_id(authorization_server_id)
+                .login_redirect_url(login_redirect_url)
+                .base_url(base_url)
+                .scopes(scopes.split_whitespace()),
+        )
+    }
+
+    /// Create a new OKTA Client that does not sign in a user, ie. to refresh tokens or for client credentials
+    pub fn without_user(
+        client_id: String,
+        authorization_server_id: String,
+        base_url: String,
+        scopes: String,
+    ) -> Result<Self, OktaClientError> {
+        OktaClient::build(
+            OktaClientBuilder::new()
+                .client_id(client_id)
+                .authorization_server_id(authorization_server_id)
+                .base_url(base_url)
+                .scopes(scopes.split_whitespace()),
+        )
+    }
+
+    /// Create a new OKTA Client from a builder
+    pub fn build(builder: OktaClientBuilder) -> Result<Self, OktaClientError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| OktaClientError::General(format!("Could not start runtime: {}", e)))?;
+
+        let inner = runtime.block_on(builder.build())?;
+
+        Ok(OktaClient { inner, runtime })
+    }
+
+    /// Get an access token for a specific OKTA tenant and client/app
+    pub fn get_access_token(&self) -> Result<String, OktaClientError> {
+        self.runtime.block_on(self.inner.get_access_token())
+    }
+
+    /// Get the access, ID and refresh tokens for a specific OKTA tenant and client/app
+    pub fn get_tokens(&self) -> Result<TokenResponse, OktaClientError> {
+        self.runtime.block_on(self.inner.get_tokens())
+    }
+
+    /// Get the tokens from the cache while the access token is valid, otherwise refresh
+    /// them with the cached refresh token, or sign in again and cache the new tokens
+    pub fn get_tokens_with_cache(
+        &self,
+        cache: &TokenCache,
+    ) -> Result<TokenResponse, OktaClientError> {
+        self.runtime
+            .block_on(self.inner.get_tokens_with_cache(cache))
+    }
+
+    /// Get the cached tokens, refreshing them if the access token has expired, None if the user
+    /// must sign in again
+    pub fn get_cached_tokens(
+        &self,
+        cache: &TokenCache,
+    ) -> Result<Option<TokenResponse>, OktaClientError> {
+        self.runtime.block_on(self.inner.get_cached_tokens(cache))
+    }
+
+    /// The key this client's tokens are cached under
+    pub fn cache_key(&self) -> TokenCacheKey {
+        self.inner.cache_key()
+    }
+
+    /// Exchange a refresh token for a new set of tokens
+    pub fn refresh(&self, refresh_token: String) -> Result<TokenResponse, OktaClientError> {
+        self.runtime.block_on(self.inner.refresh(refresh_token))
+    }
+
+    /// Get an access token for the client/app itself, for service-to-service calls
+    pub fn client_credentials(&self) -> Result<TokenResponse, OktaClientError> {
+        self.runtime.block_on(self.inner.client_credentials())
+    }
+
+    /// Verify an ID token against the JWKS, and the nonce if one was sent
+    pub fn verify_id_token(
+        &self,
+        id_token: &str,
+        nonce: Option<&str>,
+    ) -> Result<Claims, OktaClientError> {
+        self.runtime
+            .block_on(self.inner.verify_id_token(id_token, nonce))
+    }
+
+    /// Verify an access token against the JWKS, and the audience if given
+    pub fn verify_access_token(
+        &self,
+        access_token: &str,
+        audience: Option<&str>,
+    ) -> Result<Claims, OktaClientError> {
+        self.runtime
+            .block_on(self.inner.verify_access_token(access_token, audience))
+    }
+
+    /// Start a device sign in, the user then enters the user code at the verification URI
+    pub fn device_authorization(&self) -> Result<DeviceAuthorization, OktaClientError> {
+        self.runtime.block_on(self.inner.device_authorization())
+    }
+
+    /// Wait for the user to sign in with the device authorization's user code
+    pub fn get_tokens_with_device(
+        &self,
+        authorization: &DeviceAuthorization,
+    ) -> Result<TokenResponse, OktaClientError> {
+        self.runtime
+            .block_on(self.inner.get_tokens_with_device(authorization))
+    }
+
+    /// Revoke an access or refresh token, so it can't be used again
+    pub fn revoke(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+    ) -> Result<(), OktaClientError> {
+        self.runtime
+            .block_on(self.inner.revoke(token, token_type_hint))
+    }
+
+    /// Check whether a token is active on the authorization server, ie. it hasn't been revoked
+    pub fn introspect(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+    ) -> Result<TokenIntrospection, OktaClientError> {
+        self.runtime
+            .block_on(self.inner.introspect(token, token_type_hint))
+    }
+}
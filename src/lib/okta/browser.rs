@@ -0,0 +1,400 @@
+/// Sign in with the system browser, capturing the auth code on a loopback redirect URI
+use crate::okta::authorize::{
+    authorize_response, random_string, verify_nonce, AuthorizeResponse, CodeChallengeMethod,
+    OktaAuthorizeRequest, ResponseMode, ResponseType,
+};
+use crate::okta::{OktaClient, OktaClientError, TokenResponse};
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::process::Command;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Give up if the user hasn't signed in by then
+const BROWSER_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Give up on a connection that hasn't sent a request by then, ie. a browser preconnecting
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Ignore requests larger than this, the redirect only has a code and state
+const MAX_REQUEST_LENGTH: usize = 64 * 1024;
+
+const SIGNED_IN_PAGE: &str =
+    "<html><body><h3>Signed in, you can close this window.</h3></body></html>";
+const FAILED_PAGE: &str =
+    "<html><body><h3>Sign in failed, check the terminal for details.</h3></body></html>";
+
+/// Opens the authorize URL for the user, ie. in the system browser
+pub trait Browser: Send + Sync {
+    fn open(&self, url: &str) -> Result<(), OktaClientError>;
+}
+
+/// The browser named by $BROWSER, otherwise the system's default browser
+pub struct SystemBrowser;
+
+impl Browser for SystemBrowser {
+    fn open(&self, url: &str) -> Result<(), OktaClientError> {
+        let mut command = match std::env::var("BROWSER") {
+            Ok(browser) if !browser.is_empty() => Command::new(browser),
+            _ if cfg!(target_os = "macos") => Command::new("open"),
+            _ if cfg!(windows) => {
+                let mut command = Command::new("rundll32");
+                command.arg("url.dll,FileProtocolHandler");
+                command
+            }
+            _ => Command::new("xdg-open"),
+        };
+
+        command
+            .arg(url)
+            .spawn()
+            .map(|_| ())
+            .map_err(|e| OktaClientError::Browser(format!("Couldn't open a browser: {}", e)))
+    }
+}
+
+/// A local HTTP server on the redirect URI, that waits for OKTA to redirect back with the code
+pub struct LoopbackListener {
+    listener: TcpListener,
+    path: String,
+}
+
+impl LoopbackListener {
+    /// Listen on the port of a loopback redirect URI, ie. http://localhost:8080/callback
+    pub async fn bind(redirect_uri: &str) -> Result<Self, OktaClientError> {
+        let url = reqwest::Url::parse(redirect_uri)
+            .map_err(|e| OktaClientError::InvalidUrl(format!("{}: {}", redirect_uri, e)))?;
+
+        let ip = match url.host_str() {
+            Some("localhost") | Some("127.0.0.1") => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            Some("[::1]") => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            _ => {
+                return Err(OktaClientError::Configuration(format!(
+                    "The login redirect URL must be on localhost to sign in with a browser: {}",
+                    redirect_uri
+                )))
+            }
+        };
+
+        let port = match (url.scheme(), url.port()) {
+            ("http", Some(port)) => port,
+            _ => {
+                return Err(OktaClientError::Configuration(format!(
+                "The login redirect URL must be http:// with a port to sign in with a browser: {}",
+                redirect_uri
+            )))
+            }
+        };
+
+        let listener = TcpListener::bind(SocketAddr::new(ip, port))
+            .await
+            .map_err(|e| {
+                OktaClientError::Browser(format!("Couldn't listen on {}: {}", redirect_uri, e))
+            })?;
+
+        Ok(LoopbackListener {
+            listener,
+            path: url.path().to_owned(),
+        })
+    }
+
+    /// Wait for a redirect to the redirect URI with the response to an authorize request
+    /// The response can be in the query string, or posted as a form (response_mode: form_post)
+    pub(crate) async fn wait_for_response(
+        &self,
+        request: &OktaAuthorizeRequest,
+    ) -> Result<AuthorizeResponse, OktaClientError> {
+        loop {
+            let (mut stream, _) = self
+                .listener
+                .accept()
+                .await
+                .map_err(|e| OktaClientError::Browser(e.to_string()))?;
+
+            // Skip requests that can't be read in time, so an idle connection can't block the redirect
+            let params =
+                match tokio::time::timeout(READ_TIMEOUT, read_redirect(&mut stream, &self.path))
+                    .await
+                {
+                    Ok(Ok(Some(params))) => params,
+                    Ok(Ok(None)) => {
+                        respond(&mut stream, "404 Not Found", "").await;
+                        continue;
+                    }
+                    Ok(Err(_)) | Err(_) => continue,
+                };
+
+            // Not a redirect from OKTA, keep waiting
+            if !params.contains_key("error") && !params.contains_key("code") {
+                respond(&mut stream, "400 Bad Request", FAILED_PAGE).await;
+                continue;
+            }
+
+            // The same checks as a redirect read by do_oauth_authorize, the state first
+            let result = authorize_response(params, request);
+
+            match &result {
+                Ok(_) => respond(&mut stream, "200 OK", SIGNED_IN_PAGE).await,
+                Err(_) => respond(&mut stream, "400 Bad Request", FAILED_PAGE).await,
+            }
+
+            return result;
+        }
+    }
+}
+
+/// The query or form parameters of a request to the redirect path, None for other paths
+async fn read_redirect(
+    stream: &mut TcpStream,
+    path: &str,
+) -> Result<Option<HashMap<String, String>>, std::io::Error> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+
+    let mut content_length = 0;
+    let mut read = request_line.len();
+    loop {
+        let mut header = String::new();
+        read += reader.read_line(&mut header).await?;
+
+        if header.trim().is_empty() || read > MAX_REQUEST_LENGTH {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return Ok(None),
+    };
+
+    let (request_path, query) = target.split_once('?').unwrap_or((target, ""));
+    if request_path != path {
+        return Ok(None);
+    }
+
+    let mut params = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect::<HashMap<_, _>>();
+
+    if method == "POST" && content_length <= MAX_REQUEST_LENGTH {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).await?;
+        params.extend(url::form_urlencoded::parse(&body).into_owned());
+    }
+
+    Ok(Some(params))
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+
+    // The browser may have gone away, the result is still returned
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+impl OktaClient {
+    /// Open the authorize URL in a browser, then exchange the code it redirects back with
+    pub async fn get_tokens_with_browser(&self) -> Result<TokenResponse, OktaClientError> {
+        let redirect_uri = self.login_redirect_url()?;
+        let listener = LoopbackListener::bind(redirect_uri).await?;
+
+        let request = OktaAuthorizeRequest {
+            client_id: self.client_id.to_owned(),
+            response_type: ResponseType::Code,
+            code_challenge_method: CodeChallengeMethod::S256,
+            code_challenge: self.pkce.code_challenge.to_owned(),
+            redirect_uri: redirect_uri.to_owned(),
+            scope: self.scopes.to_owned(),
+            // Let the user sign in, with SSO, WebAuthn or FastPass
+            prompt: None,
+            response_mode: ResponseMode::FormPost,
+            state: random_string(),
+            nonce: random_string(),
+            session_token: None,
+        };
+
+        let url = self.authorize_url(&request)?;
+        self.browser.open(url.as_str())?;
+
+        let response = tokio::time::timeout(BROWSER_TIMEOUT, listener.wait_for_response(&request))
+            .await
+            .map_err(|_| {
+                OktaClientError::Browser("Timed out waiting to sign in with the browser".to_owned())
+            })??;
+
+        let code = match response {
+            AuthorizeResponse::Code { code, .. } => code,
+            AuthorizeResponse::Tokens(tokens) => return Ok(tokens),
+        };
+
+        let tokens = self.do_oauth_token(code).await?;
+        verify_nonce(&tokens, &request.nonce)?;
+
+        Ok(tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::AsyncReadExt;
+
+    fn request(state: &str) -> OktaAuthorizeRequest {
+        OktaAuthorizeRequest {
+            client_id: "client".to_owned(),
+            response_type: ResponseType::Code,
+            code_challenge_method: CodeChallengeMethod::S256,
+            code_challenge: "challenge".to_owned(),
+            redirect_uri: "http://127.0.0.1:0/callback".to_owned(),
+            scope: "openid".to_owned(),
+            prompt: None,
+            response_mode: ResponseMode::FormPost,
+            state: state.to_owned(),
+            nonce: "nonce".to_owned(),
+            session_token: None,
+        }
+    }
+
+    /// Send a scripted request to the listener, returning the response
+    async fn send(listener: &LoopbackListener, request: &str) -> String {
+        let mut stream = TcpStream::connect(listener.listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    fn code(response: Result<AuthorizeResponse, OktaClientError>) -> String {
+        match response {
+            Ok(AuthorizeResponse::Code { code, .. }) => code,
+            other => panic!("Expected a code, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn returns_the_code_from_a_query_string() {
+        let listener = LoopbackListener::bind("http://127.0.0.1:0/callback")
+            .await
+            .unwrap();
+        let request = request("state1");
+
+        let (result, response) = tokio::join!(
+            listener.wait_for_response(&request),
+            send(
+                &listener,
+                "GET /callback?code=code1&state=state1 HTTP/1.1\r\nHost: localhost\r\n\r\n"
+            )
+        );
+
+        assert_eq!(code(result), "code1");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[tokio::test]
+    async fn returns_the_code_from_a_form_post() {
+        let listener = LoopbackListener::bind("http://127.0.0.1:0/callback")
+            .await
+            .unwrap();
+        let request = request("state2");
+        let body = "code=code2&state=state2";
+        let redirect = format!(
+            "POST /callback HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+
+        let (result, _) = tokio::join!(
+            listener.wait_for_response(&request),
+            send(&listener, &redirect)
+        );
+
+        assert_eq!(code(result), "code2");
+    }
+
+    #[tokio::test]
+    async fn rejects_a_mismatched_state() {
+        let listener = LoopbackListener::bind("http://127.0.0.1:0/callback")
+            .await
+            .unwrap();
+        let request = request("state3");
+
+        let (result, response) = tokio::join!(
+            listener.wait_for_response(&request),
+            send(
+                &listener,
+                "GET /callback?code=code3&state=forged HTTP/1.1\r\n\r\n"
+            )
+        );
+
+        assert!(matches!(result, Err(OktaClientError::StateMismatch(_))));
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"));
+    }
+
+    #[tokio::test]
+    async fn keeps_waiting_after_another_path() {
+        let listener = LoopbackListener::bind("http://127.0.0.1:0/callback")
+            .await
+            .unwrap();
+        let request = request("state4");
+
+        let (result, (other, redirect)) =
+            tokio::join!(listener.wait_for_response(&request), async {
+                let other = send(&listener, "GET /favicon.ico HTTP/1.1\r\n\r\n").await;
+                let redirect = send(
+                    &listener,
+                    "GET /callback?code=code4&state=state4 HTTP/1.1\r\n\r\n",
+                )
+                .await;
+                (other, redirect)
+            });
+
+        assert!(other.starts_with("HTTP/1.1 404 Not Found"));
+        assert!(redirect.starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(code(result), "code4");
+    }
+
+    #[tokio::test]
+    async fn skips_an_idle_connection() {
+        let listener = LoopbackListener::bind("http://127.0.0.1:0/callback")
+            .await
+            .unwrap();
+        let request = request("state5");
+
+        // Opened first, but never sends a request
+        let _idle = TcpStream::connect(listener.listener.local_addr().unwrap())
+            .await
+            .unwrap();
+
+        let (result, response) = tokio::join!(
+            listener.wait_for_response(&request),
+            send(
+                &listener,
+                "GET /callback?code=code5&state=state5 HTTP/1.1\r\n\r\n"
+            )
+        );
+
+        assert_eq!(code(result), "code5");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+    }
+}
@@ -0,0 +1,410 @@
+/// Configure and create an OktaClient
+use crate::okta::{
+    openid, pkce, Browser, ClientAuthentication, FactorType, Flow, MfaPrompt, OktaClient,
+    OktaClientError, PasswordPrompt, ResponseMode, ResponseType, SystemBrowser, TokenCacheKey,
+    Totp,
+};
+
+const DEFAULT_AUTHORIZATION_SERVER_ID: &str = "default";
+const DEFAULT_SCOPES: [&str; 3] = ["openid", "profile", "email"];
+const MAX_REDIRECTS: usize = 10;
+
+pub struct OktaClientBuilder {
+    base_url: Option<String>,
+    client_id: Option<String>,
+    authorization_server_id: String,
+    issuer: Option<String>,
+    login_redirect_url: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    scopes: Vec<String>,
+    client_authentication: ClientAuthentication,
+    flow: Flow,
+    response_type: ResponseType,
+    response_mode: ResponseMode,
+    factor: Option<FactorType>,
+    totp_seed: Option<String>,
+    http_client: Option<reqwest::Client>,
+    mfa_prompt: Option<Box<dyn MfaPrompt>>,
+    password_prompt: Option<Box<dyn PasswordPrompt>>,
+    browser: Option<Box<dyn Browser>>,
+}
+
+impl Default for OktaClientBuilder {
+    fn default() -> Self {
+        OktaClientBuilder {
+            base_url: None,
+            client_id: None,
+            authorization_server_id: DEFAULT_AUTHORIZATION_SERVER_ID.to_owned(),
+            issuer: None,
+            login_redirect_url: None,
+            username: None,
+            password: None,
+            scopes: DEFAULT_SCOPES.iter().map(|s| s.to_string()).collect(),
+            client_authentication: ClientAuthentication::None,
+            flow: Flow::default(),
+            response_type: ResponseType::default(),
+            response_mode: ResponseMode::default(),
+            factor: None,
+            totp_seed: None,
+            http_client: None,
+            mfa_prompt: None,
+            password_prompt: None,
+            browser: None,
+        }
+    }
+}
+
+impl OktaClientBuilder {
+    pub fn new() -> Self {
+        OktaClientBuilder::default()
+    }
+
+    /// Base URL of the OKTA Tenant (ie. https://myapp.okta.com/), required unless an issuer is given
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    /// The OKTA Client ID associated with the app, required
+    pub fn client_id(mut self, client_id: impl Into<String>) -> Self {
+        self.client_id = Some(client_id.into());
+        self
+    }
+
+    /// The ID of a custom Authorization Server, defaults to "default"
+    pub fn authorization_server_id(mut self, authorization_server_id: impl Into<String>) -> Self {
+        self.authorization_server_id = authorization_server_id.into();
+        self
+    }
+
+    /// The issuer URL of the authorization server, instead of the authorization server ID
+    /// ie. https://myapp.okta.com for the org authorization server, or a custom domain
+    /// The base URL defaults to the issuer's origin
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// OKTA Login Redirect URL associated with the app, required by Flow::Authn and Flow::Idx
+    pub fn login_redirect_url(mut self, login_redirect_url: impl Into<String>) -> Self {
+        self.login_redirect_url = Some(login_redirect_url.into());
+        self
+    }
+
+    /// The user to sign in, required by Flow::Authn, Flow::Password and Flow::Idx
+    pub fn credentials(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+
+    /// The user whose tokens are cached, without a password, ie. to refresh cached tokens
+    pub fn username(mut self, username: impl Into<String>) -> Self {
+        self.username = Some(username.into());
+        self
+    }
+
+    /// The scopes to request, defaults to openid, profile and email
+    pub fn scopes<I, S>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.scopes = scopes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Authenticate the client/app at the token endpoint, ie. with a client secret
+    pub fn client_authentication(mut self, client_authentication: ClientAuthentication) -> Self {
+        self.client_authentication = client_authentication;
+        self
+    }
+
+    /// How the user signs in, defaults to Flow::Authn
+    pub fn flow(mut self, flow: Flow) -> Self {
+        self.flow = flow;
+        self
+    }
+
+    /// What Flow::Authn gets from the authorize endpoint, defaults to ResponseType::Code
+    /// ResponseType::Token and ResponseType::IdToken need an app that allows the implicit grant
+    pub fn response_type(mut self, response_type: ResponseType) -> Self {
+        self.response_type = response_type;
+        self
+    }
+
+    /// How Flow::Authn gets the authorize response, defaults to ResponseMode::FormPost
+    pub fn response_mode(mut self, response_mode: ResponseMode) -> Self {
+        self.response_mode = response_mode;
+        self
+    }
+
+    /// The factor to verify when several are enrolled, defaults to the first of
+    /// TOTP, push, SMS, voice call and email
+    pub fn factor(mut self, factor: FactorType) -> Self {
+        self.factor = Some(factor);
+        self
+    }
+
+    /// Generate TOTP passcodes from the base32 seed of a Google Authenticator or Okta Verify
+    /// factor instead of asking for them, ie. for test accounts used in CI
+    pub fn totp_seed(mut self, totp_seed: impl Into<String>) -> Self {
+        self.totp_seed = Some(totp_seed.into());
+        self
+    }
+
+    /// Ask for a passcode when the user has MFA enrolled, ie. a Passcode passed as a flag
+    pub fn mfa_prompt(mut self, mfa_prompt: impl MfaPrompt + 'static) -> Self {
+        self.mfa_prompt = Some(Box::new(mfa_prompt));
+        self
+    }
+
+    /// Ask for a new password when it has expired, and warn before it expires
+    pub fn password_prompt(mut self, password_prompt: impl PasswordPrompt + 'static) -> Self {
+        self.password_prompt = Some(Box::new(password_prompt));
+        self
+    }
+
+    /// Open the authorize URL for Flow::Browser, defaults to the system browser
+    pub fn browser(mut self, browser: impl Browser + 'static) -> Self {
+        self.browser = Some(Box::new(browser));
+        self
+    }
+
+    /// Use an existing HTTP client, ie. with a proxy or custom timeouts
+    /// For ResponseMode::Query and ResponseMode::Fragment it must not follow redirects to
+    /// the login redirect URL, ie. with reqwest::redirect::Policy::none()
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// The key the client's tokens are cached under, the same as OktaClient::cache_key
+    /// Available before building, so the cache can be checked without going to OKTA
+    pub fn cache_key(&self) -> Result<TokenCacheKey, OktaClientError> {
+        let (base_url, _) = self.urls()?;
+
+        Ok(TokenCacheKey::new(
+            base_url.to_string(),
+            self.issuer
+                .to_owned()
+                .unwrap_or_else(|| self.authorization_server_id.to_owned()),
+            self.required_client_id()?.to_owned(),
+            self.username.to_owned().unwrap_or_default(),
+            self.scopes.join(" "),
+        ))
+    }
+
+    /// Get the OpenID configuration of the authorization server and create the client
+    pub async fn build(self) -> Result<OktaClient, OktaClientError> {
+        let (base_url, issuer) = self.urls()?;
+        let client_id = self.required_client_id()?.to_owned();
+        let totp = self
+            .totp_seed
+            .as_deref()
+            .map(Totp::from_base32)
+            .transpose()?;
+        if self.response_mode == ResponseMode::Query && self.response_type != ResponseType::Code {
+            return Err(OktaClientError::Configuration(format!(
+                "The {} response mode can only be used with the code response type",
+                self.response_mode
+            )));
+        }
+        let http = match self.http_client {
+            Some(http) => http,
+            None => default_http_client(self.login_redirect_url.to_owned())?,
+        };
+
+        let openid_config = openid::get_openid_config(&http, &issuer).await?;
+
+        // Fail before signing in if the authorization server can't do what was asked for
+        openid_config.validate_scopes(&self.scopes)?;
+        openid_config.validate_flow(self.flow, self.response_type, self.response_mode)?;
+
+        Ok(OktaClient {
+            client_id,
+            base_url,
+            // Tokens from an issuer URL are cached under it instead
+            authorization_server_id: self.issuer.unwrap_or(self.authorization_server_id),
+            login_redirect_url: self.login_redirect_url,
+            username: self.username,
+            password: self.password,
+            openid_config,
+            jwks: Default::default(),
+            pkce: pkce::Pkce::new(),
+            scopes: self.scopes.join(" "),
+            client_authentication: self.client_authentication,
+            flow: self.flow,
+            response_type: self.response_type,
+            response_mode: self.response_mode,
+            factor: self.factor,
+            totp,
+            http,
+            mfa_prompt: self.mfa_prompt,
+            password_prompt: self.password_prompt,
+            browser: self.browser.unwrap_or_else(|| Box::new(SystemBrowser)),
+        })
+    }
+
+    /// The base URL and issuer, each defaulting to one worked out from the other
+    fn urls(&self) -> Result<(reqwest::Url, reqwest::Url), OktaClientError> {
+        let issuer = self.issuer.as_deref().map(parse_url).transpose()?;
+        let base_url = match (&self.base_url, &issuer) {
+            (Some(base_url), _) => parse_url(base_url)?,
+            (None, Some(issuer)) => parse_url(&issuer.origin().ascii_serialization())?,
+            (None, None) => {
+                return Err(OktaClientError::Configuration(
+                    "A base URL or issuer is required".to_owned(),
+                ))
+            }
+        };
+        let issuer = match issuer {
+            Some(issuer) => issuer,
+            None => parse_url(&format!(
+                "{}oauth2/{}",
+                base_url, self.authorization_server_id
+            ))?,
+        };
+
+        Ok((base_url, issuer))
+    }
+
+    fn required_client_id(&self) -> Result<&str, OktaClientError> {
+        self.client_id
+            .as_deref()
+            .ok_or_else(|| OktaClientError::Configuration("A client ID is required".to_owned()))
+    }
+}
+
+/// Follows redirects, except to the login redirect URL, which is read from the authorize response
+fn default_http_client(
+    login_redirect_url: Option<String>,
+) -> Result<reqwest::Client, OktaClientError> {
+    // An empty or invalid redirect URL can't match anything, so every redirect is followed
+    let login_redirect_url = login_redirect_url.and_then(|url| reqwest::Url::parse(&url).ok());
+
+    let policy = reqwest::redirect::Policy::custom(move |attempt| match &login_redirect_url {
+        Some(url)
+            if attempt.url().origin() == url.origin() && attempt.url().path() == url.path() =>
+        {
+            attempt.stop()
+        }
+        _ if attempt.previous().len() >= MAX_REDIRECTS => attempt.error("Too many redirects"),
+        _ => attempt.follow(),
+    });
+
+    Ok(reqwest::Client::builder().redirect(policy).build()?)
+}
+
+/// Parse a http(s) URL, ending with a slash so paths can be joined to it
+fn parse_url(url: &str) -> Result<reqwest::Url, OktaClientError> {
+    let mut parsed = reqwest::Url::parse(url)
+        .map_err(|e| OktaClientError::InvalidUrl(format!("{}: {}", url, e)))?;
+
+    if parsed.scheme() != "https" && parsed.scheme() != "http" {
+        return Err(OktaClientError::InvalidUrl(format!(
+            "{}: must be a http or https URL",
+            url
+        )));
+    }
+
+    if !parsed.path().ends_with('/') {
+        let path = format!("{}/", parsed.path());
+        parsed.set_path(&path);
+    }
+
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(builder: OktaClientBuilder) -> (String, String) {
+        let (base_url, issuer) = builder.urls().unwrap();
+        (base_url.to_string(), issuer.to_string())
+    }
+
+    #[test]
+    fn joins_the_authorization_server_to_the_base_url() {
+        for base_url in ["https://x.okta.com", "https://x.okta.com/"] {
+            assert_eq!(
+                urls(OktaClient::builder().base_url(base_url)),
+                (
+                    "https://x.okta.com/".to_owned(),
+                    "https://x.okta.com/oauth2/default/".to_owned()
+                )
+            );
+        }
+
+        assert_eq!(
+            urls(
+                OktaClient::builder()
+                    .base_url("https://x.okta.com")
+                    .authorization_server_id("abc123")
+            )
+            .1,
+            "https://x.okta.com/oauth2/abc123/"
+        );
+    }
+
+    #[test]
+    fn uses_the_issuer_for_the_org_authorization_server() {
+        assert_eq!(
+            urls(OktaClient::builder().issuer("https://x.okta.com")),
+            (
+                "https://x.okta.com/".to_owned(),
+                "https://x.okta.com/".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn defaults_the_base_url_to_the_origin_of_the_issuer() {
+        assert_eq!(
+            urls(OktaClient::builder().issuer("https://login.example.com/oauth2/default")),
+            (
+                "https://login.example.com/".to_owned(),
+                "https://login.example.com/oauth2/default/".to_owned()
+            )
+        );
+
+        // A base URL that is given is kept, ie. an issuer on a custom domain
+        assert_eq!(
+            urls(
+                OktaClient::builder()
+                    .base_url("https://x.okta.com")
+                    .issuer("https://login.example.com/oauth2/default")
+            ),
+            (
+                "https://x.okta.com/".to_owned(),
+                "https://login.example.com/oauth2/default/".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn requires_a_base_url_or_issuer() {
+        assert!(matches!(
+            OktaClient::builder().urls(),
+            Err(OktaClientError::Configuration(_))
+        ));
+    }
+
+    #[test]
+    fn parses_only_http_urls() {
+        assert_eq!(
+            parse_url("http://localhost:8080/okta").unwrap().as_str(),
+            "http://localhost:8080/okta/"
+        );
+        assert!(matches!(
+            parse_url("ftp://x.okta.com"),
+            Err(OktaClientError::InvalidUrl(_))
+        ));
+        assert!(matches!(
+            parse_url("x.okta.com"),
+            Err(OktaClientError::InvalidUrl(_))
+        ));
+    }
+}
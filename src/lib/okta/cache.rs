@@ -0,0 +1,188 @@
+/// Cache tokens on disk between runs
+use crate::okta::{OktaClientError, SecretStore, TokenResponse};
+
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Treat tokens as expired slightly early, so they are still valid when used
+const EXPIRY_LEEWAY_SECONDS: i64 = 60;
+
+/// Identifies a set of tokens in the cache
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenCacheKey {
+    pub base_url: String,
+    /// The authorization server ID, or the issuer URL if one was given
+    pub authorization_server_id: String,
+    pub client_id: String,
+    pub username: String,
+    pub scopes: String,
+}
+
+impl TokenCacheKey {
+    /// URLs are compared without a trailing slash
+    pub fn new(
+        base_url: String,
+        authorization_server_id: String,
+        client_id: String,
+        username: String,
+        scopes: String,
+    ) -> Self {
+        TokenCacheKey {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            authorization_server_id: authorization_server_id.trim_end_matches('/').to_owned(),
+            client_id,
+            username,
+            scopes,
+        }
+    }
+
+    /// The name the refresh token is stored under in a secret store
+    pub fn refresh_token_secret_name(&self) -> String {
+        format!(
+            "refresh_token:{}:{}:{}:{}:{}",
+            self.base_url, self.authorization_server_id, self.client_id, self.username, self.scopes
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CachedTokens {
+    pub key: TokenCacheKey,
+    pub tokens: TokenResponse,
+    /// When the access token expires, as a unix timestamp
+    pub expires_at: i64,
+}
+
+impl CachedTokens {
+    pub fn is_expired(&self) -> bool {
+        chrono::Utc::now().timestamp() + EXPIRY_LEEWAY_SECONDS >= self.expires_at
+    }
+}
+
+/// A JSON file of tokens, keyed by tenant, client, user and scopes
+pub struct TokenCache {
+    path: PathBuf,
+    secret_store: Option<Arc<dyn SecretStore>>,
+}
+
+impl TokenCache {
+    pub fn new(path: PathBuf) -> Self {
+        TokenCache {
+            path,
+            secret_store: None,
+        }
+    }
+
+    /// Keep refresh tokens in a secret store instead of the cache file
+    pub fn with_secret_store(mut self, secret_store: Arc<dyn SecretStore>) -> Self {
+        self.secret_store = Some(secret_store);
+        self
+    }
+
+    /// The cache file in the user's cache directory, ie. ~/.cache/auth-token/tokens.json
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("auth-token").join("tokens.json"))
+    }
+
+    /// Get the cached tokens for a key, even if they have expired
+    /// Only the refresh token for this key is read from the secret store
+    pub fn get(&self, key: &TokenCacheKey) -> Result<Option<CachedTokens>, OktaClientError> {
+        let mut entry = match self.load()?.into_iter().find(|entry| &entry.key == key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        if let Some(secret_store) = &self.secret_store {
+            entry.tokens.refresh_token = secret_store.get(&key.refresh_token_secret_name())?;
+        }
+
+        Ok(Some(entry))
+    }
+
+    /// Store tokens for a key, replacing any tokens already cached
+    pub fn put(&self, key: &TokenCacheKey, tokens: &TokenResponse) -> Result<(), OktaClientError> {
+        let mut tokens = tokens.to_owned();
+
+        if let Some(secret_store) = &self.secret_store {
+            let name = key.refresh_token_secret_name();
+            match tokens.refresh_token.take() {
+                Some(refresh_token) => secret_store.set(&name, &refresh_token)?,
+                None => secret_store.delete(&name)?,
+            }
+        }
+
+        let mut entries = self.load()?;
+        entries.retain(|entry| &entry.key != key);
+        entries.push(CachedTokens {
+            key: key.to_owned(),
+            expires_at: chrono::Utc::now().timestamp() + tokens.expires_in as i64,
+            tokens,
+        });
+
+        self.save(&entries)
+    }
+
+    /// Remove the tokens for a key
+    pub fn remove(&self, key: &TokenCacheKey) -> Result<(), OktaClientError> {
+        if let Some(secret_store) = &self.secret_store {
+            secret_store.delete(&key.refresh_token_secret_name())?;
+        }
+
+        let mut entries = self.load()?;
+        entries.retain(|entry| &entry.key != key);
+
+        self.save(&entries)
+    }
+
+    /// All cached tokens
+    pub fn entries(&self) -> Result<Vec<CachedTokens>, OktaClientError> {
+        let mut entries = self.load()?;
+
+        if let Some(secret_store) = &self.secret_store {
+            for entry in entries.iter_mut() {
+                entry.tokens.refresh_token =
+                    secret_store.get(&entry.key.refresh_token_secret_name())?;
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn load(&self) -> Result<Vec<CachedTokens>, OktaClientError> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+
+        let text = std::fs::read_to_string(&self.path).map_err(|e| self.error(e))?;
+
+        serde_json::from_str(&text).map_err(|e| self.error(e))
+    }
+
+    fn save(&self, entries: &[CachedTokens]) -> Result<(), OktaClientError> {
+        let text = serde_json::to_string_pretty(entries).map_err(|e| self.error(e))?;
+
+        // Tokens are credentials, so only the current user may read them
+        write_private_file(&self.path, text.as_bytes()).map_err(|e| self.error(e))
+    }
+
+    fn error(&self, error: impl std::fmt::Display) -> OktaClientError {
+        OktaClientError::Cache(format!("{}: {}", self.path.display(), error))
+    }
+}
+
+/// Write a file that only the current user may read, creating its directory if needed
+pub(crate) fn write_private_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(contents)
+}
@@ -0,0 +1,93 @@
+/// Authenticate the client/app at the OAuth token endpoint
+use crate::okta::authorize::random_string;
+use crate::okta::OktaClientError;
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+
+const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// Lifetime of a client assertion, in seconds
+const CLIENT_ASSERTION_LIFETIME: i64 = 300;
+
+#[derive(Debug, Clone)]
+pub enum ClientAuthentication {
+    /// Public client, only the client_id is sent
+    None,
+    /// Confidential client using client_secret_post
+    ClientSecret(String),
+    /// Confidential client using private_key_jwt, signed with an RS256 private key in PEM format
+    PrivateKeyJwt {
+        private_key_pem: String,
+        key_id: Option<String>,
+    },
+}
+
+/// The client authentication parameters sent along with a token request
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ClientAuthenticationParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_assertion_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_assertion: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ClientAssertionClaims {
+    iss: String,
+    sub: String,
+    aud: String,
+    iat: i64,
+    exp: i64,
+    jti: String,
+}
+
+impl ClientAuthentication {
+    /// Build the parameters to authenticate as client_id at the token endpoint
+    pub fn as_params(
+        &self,
+        client_id: &str,
+        token_endpoint: &str,
+    ) -> Result<ClientAuthenticationParams, OktaClientError> {
+        match self {
+            ClientAuthentication::None => Ok(ClientAuthenticationParams::default()),
+            ClientAuthentication::ClientSecret(client_secret) => Ok(ClientAuthenticationParams {
+                client_secret: Some(client_secret.to_owned()),
+                ..Default::default()
+            }),
+            ClientAuthentication::PrivateKeyJwt {
+                private_key_pem,
+                key_id,
+            } => {
+                let key = EncodingKey::from_rsa_pem(private_key_pem.as_bytes()).map_err(|e| {
+                    OktaClientError::Configuration(format!("Invalid private key: {}", e))
+                })?;
+
+                let mut header = Header::new(Algorithm::RS256);
+                header.kid = key_id.to_owned();
+
+                let now = chrono::Utc::now().timestamp();
+                let claims = ClientAssertionClaims {
+                    iss: client_id.to_owned(),
+                    sub: client_id.to_owned(),
+                    aud: token_endpoint.to_owned(),
+                    iat: now,
+                    exp: now + CLIENT_ASSERTION_LIFETIME,
+                    jti: random_string(),
+                };
+
+                let assertion = jsonwebtoken::encode(&header, &claims, &key).map_err(|e| {
+                    OktaClientError::General(format!("Could not sign client assertion: {}", e))
+                })?;
+
+                Ok(ClientAuthenticationParams {
+                    client_assertion_type: Some(CLIENT_ASSERTION_TYPE.to_owned()),
+                    client_assertion: Some(assertion),
+                    ..Default::default()
+                })
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+/// Sign in on another device with the OAuth Device Authorization grant (RFC 8628)
+use crate::okta::client_auth::ClientAuthenticationParams;
+use crate::okta::token::{GrantType, OktaTokenError, OktaTokenRequest};
+use crate::okta::{OktaClient, OktaClientError, TokenResponse};
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How long to wait between polls if the authorization server doesn't say, in seconds
+const DEFAULT_INTERVAL: u64 = 5;
+
+/// How much longer to wait between polls when asked to slow down, in seconds
+const SLOW_DOWN_INTERVAL: u64 = 5;
+
+/// The codes to show the user, returned by the device authorization endpoint
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    /// The code the user enters at the verification URI
+    pub user_code: String,
+    pub verification_uri: String,
+    /// The verification URI with the user code already filled in, ie. for a QR code
+    pub verification_uri_complete: Option<String>,
+    /// Lifetime of the device and user codes, in seconds
+    pub expires_in: u64,
+    /// Seconds to wait between polls of the token endpoint
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_interval() -> u64 {
+    DEFAULT_INTERVAL
+}
+
+#[derive(Serialize, Debug)]
+struct OktaDeviceAuthorizationRequest {
+    client_id: String,
+    scope: String,
+    #[serde(flatten)]
+    client_authentication: ClientAuthenticationParams,
+}
+
+impl OktaClient {
+    /// Start a device sign in, the user then enters the user code at the verification URI
+    pub async fn device_authorization(&self) -> Result<DeviceAuthorization, OktaClientError> {
+        let endpoint = self
+            .openid_config
+            .device_authorization_endpoint
+            .as_deref()
+            .ok_or_else(|| {
+                OktaClientError::Configuration(
+                    "The authorization server doesn't support the Device Authorization grant"
+                        .to_owned(),
+                )
+            })?;
+
+        self.openid_config
+            .validate_grant_type("urn:ietf:params:oauth:grant-type:device_code")?;
+
+        let request = OktaDeviceAuthorizationRequest {
+            client_id: self.client_id.to_owned(),
+            scope: self.scopes.to_owned(),
+            client_authentication: self
+                .client_authentication
+                .as_params(&self.client_id, endpoint)?,
+        };
+
+        let text = self
+            .http
+            .post(endpoint)
+            .form(&request)
+            .send()
+            .await?
+            .text()
+            .await?;
+
+        match serde_json::from_str::<DeviceAuthorization>(&text) {
+            Ok(authorization) => Ok(authorization),
+            Err(_) => match serde_json::from_str::<OktaTokenError>(&text) {
+                Ok(error) => Err(error.into()),
+                Err(e) => Err(OktaClientError::Parser(format!(
+                    "Invalid device authorization response ({}): {}",
+                    e, text
+                ))),
+            },
+        }
+    }
+
+    /// Poll the token endpoint until the user has signed in with the device authorization's user code
+    pub async fn get_tokens_with_device(
+        &self,
+        authorization: &DeviceAuthorization,
+    ) -> Result<TokenResponse, OktaClientError> {
+        let expires_at = Instant::now() + Duration::from_secs(authorization.expires_in);
+        let mut interval = authorization.interval;
+
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+
+            if Instant::now() >= expires_at {
+                return Err(OktaClientError::DeviceAuthorization(
+                    "The user code expired before signing in".to_owned(),
+                ));
+            }
+
+            let mut request =
+                OktaTokenRequest::new(self.client_id.to_owned(), GrantType::DeviceCode);
+            request.device_code = Some(authorization.device_code.to_owned());
+
+            let error = match self.send_token_request(request).await? {
+                Ok(tokens) => return Ok(tokens),
+                Err(error) => error,
+            };
+
+            match error.error.as_str() {
+                "authorization_pending" => continue,
+                "slow_down" => interval += SLOW_DOWN_INTERVAL,
+                "access_denied" => {
+                    return Err(OktaClientError::DeviceAuthorization(
+                        "The sign in was denied".to_owned(),
+                    ))
+                }
+                "expired_token" => {
+                    return Err(OktaClientError::DeviceAuthorization(
+                        "The user code expired before signing in".to_owned(),
+                    ))
+                }
+                _ => return Err(error.into()),
+            }
+        }
+    }
+}
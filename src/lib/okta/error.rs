@@ -1,10 +1,65 @@
+use serde::Deserialize;
+
 #[derive(Debug)]
 pub enum OktaClientError {
     Unknown,
     General(String),
+    Configuration(String),
+    InvalidUrl(String),
     Network(String),
     OktaAPI(String),
     Parser(String),
+    MissingSessionToken(String),
+    Mfa(String),
+    /// The account is locked after too many failed sign in attempts
+    LockedOut(String),
+    /// The password has expired and wasn't changed
+    PasswordExpired(String),
+    /// The password must be reset, ie. by an administrator
+    PasswordReset(String),
+    /// The user must enroll an MFA factor in OKTA first
+    MfaEnroll(String),
+    /// The authn transaction has no user, ie. the state token expired
+    Unauthenticated(String),
+    /// An authn status that can't be handled
+    AuthnStatus(String),
+    /// A step of the Identity Engine (IDX) API that can't be handled
+    Idx(String),
+    /// Signing in with the browser failed, ie. the redirect listener couldn't start
+    Browser(String),
+    /// The state sent back to the redirect URL isn't the one sent, ie. a forged redirect
+    StateMismatch(String),
+    /// The nonce in the ID token isn't the one sent, ie. a replayed ID token
+    NonceMismatch(String),
+    /// A token failed verification against the JWKS, ie. a bad signature or it expired
+    InvalidToken(String),
+    /// The device sign in was denied or expired before the user signed in
+    DeviceAuthorization(String),
+    Cache(String),
+    SecretStore(String),
+}
+
+/// The JSON body of an error from the OKTA API
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OktaApiError {
+    pub error_code: String,
+    pub error_summary: Option<String>,
+}
+
+impl OktaClientError {
+    /// Read an OKTA API error from a JSON response body, if it is one
+    pub(crate) fn from_okta_api_error(text: &str) -> Option<Self> {
+        serde_json::from_str::<OktaApiError>(text)
+            .ok()
+            .map(|error| {
+                OktaClientError::OktaAPI(format!(
+                    "{} {}",
+                    error.error_code,
+                    error.error_summary.unwrap_or_default()
+                ))
+            })
+    }
 }
 
 impl From<reqwest::Error> for OktaClientError {
@@ -13,6 +68,12 @@ impl From<reqwest::Error> for OktaClientError {
     }
 }
 
+impl From<serde_json::Error> for OktaClientError {
+    fn from(error: serde_json::Error) -> Self {
+        OktaClientError::Parser(error.to_string())
+    }
+}
+
 impl From<String> for OktaClientError {
     fn from(error: String) -> Self {
         OktaClientError::General(error.to_owned())
@@ -24,9 +85,43 @@ impl std::fmt::Display for OktaClientError {
         match self {
             OktaClientError::Unknown => f.write_str("Unknown Error"),
             OktaClientError::General(e) => f.write_str(&format!("General Error: {}", e)),
+            OktaClientError::Configuration(e) => {
+                f.write_str(&format!("Configuration Error: {}", e))
+            }
+            OktaClientError::InvalidUrl(e) => f.write_str(&format!("Invalid URL: {}", e)),
             OktaClientError::Network(e) => f.write_str(&format!("Network Error: {}", e)),
             OktaClientError::OktaAPI(e) => f.write_str(&format!("OKTA API Error: {}", e)),
             OktaClientError::Parser(e) => f.write_str(&format!("Parser Error: {}", e)),
+            OktaClientError::MissingSessionToken(e) => {
+                f.write_str(&format!("No session token was returned: {}", e))
+            }
+            OktaClientError::Mfa(e) => f.write_str(&format!("MFA Error: {}", e)),
+            OktaClientError::LockedOut(e) => f.write_str(&format!("Account Locked Out: {}", e)),
+            OktaClientError::PasswordExpired(e) => f.write_str(&format!("Password Expired: {}", e)),
+            OktaClientError::PasswordReset(e) => {
+                f.write_str(&format!("Password Reset Required: {}", e))
+            }
+            OktaClientError::MfaEnroll(e) => {
+                f.write_str(&format!("MFA Enrollment Required: {}", e))
+            }
+            OktaClientError::Unauthenticated(e) => {
+                f.write_str(&format!("Not Authenticated: {}", e))
+            }
+            OktaClientError::AuthnStatus(e) => {
+                f.write_str(&format!("Unsupported Authn Status: {}", e))
+            }
+            OktaClientError::Idx(e) => f.write_str(&format!("IDX Error: {}", e)),
+            OktaClientError::Browser(e) => f.write_str(&format!("Browser Error: {}", e)),
+            OktaClientError::StateMismatch(e) => f.write_str(&format!("State Mismatch: {}", e)),
+            OktaClientError::NonceMismatch(e) => f.write_str(&format!("Nonce Mismatch: {}", e)),
+            OktaClientError::InvalidToken(e) => f.write_str(&format!("Invalid Token: {}", e)),
+            OktaClientError::DeviceAuthorization(e) => {
+                f.write_str(&format!("Device Authorization Error: {}", e))
+            }
+            OktaClientError::Cache(e) => f.write_str(&format!("Token Cache Error: {}", e)),
+            OktaClientError::SecretStore(e) => f.write_str(&format!("Secret Store Error: {}", e)),
         }
     }
 }
+
+impl std::error::Error for OktaClientError {}
@@ -0,0 +1,45 @@
+/// How a user signs in to get tokens
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Flow {
+    /// Get a session token from /api/v1/authn, then an auth code from /authorize
+    #[default]
+    Authn,
+    /// Post the username and password straight to the token endpoint (grant_type: password)
+    /// The app must allow the Resource Owner Password grant
+    Password,
+    /// Sign in with the OKTA Identity Engine (IDX) API, then exchange the interaction code
+    /// For orgs on Identity Engine that restrict /api/v1/authn
+    Idx,
+    /// Sign in with the system browser, capturing the auth code on a loopback redirect URI
+    /// For SSO federation, WebAuthn and FastPass
+    Browser,
+}
+
+impl Flow {
+    pub const NAMES: [&'static str; 4] = ["authn", "password", "idx", "browser"];
+}
+
+impl std::fmt::Display for Flow {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Flow::Authn => f.write_str("authn"),
+            Flow::Password => f.write_str("password"),
+            Flow::Idx => f.write_str("idx"),
+            Flow::Browser => f.write_str("browser"),
+        }
+    }
+}
+
+impl std::str::FromStr for Flow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "authn" => Ok(Flow::Authn),
+            "password" => Ok(Flow::Password),
+            "idx" => Ok(Flow::Idx),
+            "browser" => Ok(Flow::Browser),
+            _ => Err(format!("Unknown flow {}", s)),
+        }
+    }
+}
@@ -0,0 +1,655 @@
+/// Sign in with the OKTA Identity Engine (IDX) API, for orgs that restrict /api/v1/authn
+use crate::okta::authorize::random_string;
+use crate::okta::client_auth::ClientAuthenticationParams;
+use crate::okta::mfa::{ChallengeAnswer, FactorType, OktaFactor, PushStatus, PUSH_TIMEOUT};
+use crate::okta::{OktaClient, OktaClientError, TokenResponse};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+const IDX_CONTENT_TYPE: &str = "application/ion+json; okta-version=1.0.0";
+
+/// Give up if signing in takes more steps than this, ie. if a remediation keeps failing
+const MAX_REMEDIATIONS: usize = 20;
+
+/// How often to poll for the result of a push, if OKTA doesn't say
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(4);
+
+/// The remediations that can be taken, in the order to take them when several are offered and
+/// none follows on from the last step, ie. select is offered alongside a challenge to verify with
+/// something else
+const REMEDIATIONS: [&str; 4] = [
+    "identify",
+    "challenge-poll",
+    "challenge-authenticator",
+    "select-authenticator-authenticate",
+];
+
+#[derive(Serialize, Debug)]
+struct IdxInteractRequest {
+    client_id: String,
+    scope: String,
+    code_challenge: String,
+    code_challenge_method: String,
+    redirect_uri: String,
+    state: String,
+    #[serde(flatten)]
+    client_authentication: ClientAuthenticationParams,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct IdxInteractResponse {
+    interaction_handle: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// A response from the /idp/idx endpoints, ie. what to do next to sign in
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IdxResponse {
+    pub state_handle: Option<String>,
+    pub remediation: IdxCollection<IdxRemediation>,
+    pub messages: IdxCollection<IdxMessage>,
+    pub authenticators: IdxCollection<IdxAuthenticator>,
+    pub current_authenticator_enrollment: Option<IdxObject<IdxAuthenticator>>,
+    pub current_authenticator: Option<IdxObject<IdxAuthenticator>>,
+    /// Present once the user has signed in
+    pub success_with_interaction_code: Option<IdxRemediation>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IdxCollection<T> {
+    pub value: Vec<T>,
+}
+
+impl<T> Default for IdxCollection<T> {
+    fn default() -> Self {
+        IdxCollection { value: Vec::new() }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IdxObject<T> {
+    pub value: T,
+}
+
+/// A step that can be taken, ie. identify or challenge-authenticator
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct IdxRemediation {
+    pub name: String,
+    pub href: String,
+    pub value: Vec<IdxField>,
+    /// How long to wait before polling, in milliseconds
+    pub refresh: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct IdxField {
+    pub name: String,
+    pub value: Value,
+    pub options: Vec<IdxOption>,
+    pub form: Option<IdxCollection<IdxField>>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct IdxOption {
+    pub label: String,
+    pub value: Value,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IdxAuthenticator {
+    pub id: String,
+    /// ie. password, app, email or phone_number
+    #[serde(rename = "type")]
+    pub authenticator_type: String,
+    /// ie. okta_verify or google_otp
+    pub key: String,
+    pub display_name: String,
+    pub profile: IdxAuthenticatorProfile,
+    pub resend: Option<IdxRemediation>,
+    pub contextual_data: IdxContextualData,
+    /// The methods of an enrollment, ie. totp for an Okta Verify code
+    pub methods: Vec<IdxMethod>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct IdxMethod {
+    #[serde(rename = "type")]
+    pub method_type: String,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IdxAuthenticatorProfile {
+    pub email: Option<String>,
+    pub phone_number: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct IdxContextualData {
+    /// The number to pick in Okta Verify, if number challenge is enabled
+    pub correct_answer: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct IdxMessage {
+    pub message: String,
+    pub class: String,
+}
+
+impl IdxRemediation {
+    fn field(&self, name: &str) -> Option<&IdxField> {
+        self.value.iter().find(|field| field.name == name)
+    }
+}
+
+impl IdxField {
+    /// The fields of an option's form, ie. the id and methodType of an authenticator
+    fn option_fields(option: &IdxOption) -> Vec<IdxField> {
+        option
+            .value
+            .get("form")
+            .and_then(|form| serde_json::from_value::<IdxCollection<IdxField>>(form.clone()).ok())
+            .map(|form| form.value)
+            .unwrap_or_default()
+    }
+
+    /// The field's value, or the values of its options, ie. totp and push for Okta Verify
+    fn values(&self) -> Vec<String> {
+        match self.value.as_str() {
+            Some(value) => vec![value.to_owned()],
+            None => self
+                .options
+                .iter()
+                .filter_map(|option| option.value.as_str().map(str::to_owned))
+                .collect(),
+        }
+    }
+}
+
+impl IdxAuthenticator {
+    /// The factor to show the user and ask the MFA prompt about
+    fn as_factor(&self, kind: FactorType) -> OktaFactor {
+        let mut factor = OktaFactor {
+            id: self.id.to_owned(),
+            factor_type: match kind {
+                FactorType::Totp => "token:software:totp",
+                FactorType::Push => "push",
+                FactorType::Sms => "sms",
+                FactorType::Call => "call",
+                FactorType::Email => "email",
+            }
+            .to_owned(),
+            provider: match self.key.as_str() {
+                "google_otp" => "GOOGLE",
+                _ => "OKTA",
+            }
+            .to_owned(),
+            ..OktaFactor::default()
+        };
+        factor.profile.email = self.profile.email.to_owned();
+        factor.profile.phone_number = self.profile.phone_number.to_owned();
+        factor
+    }
+
+    /// The name of the credential a code is sent as, Okta Verify codes are sent as totp
+    fn credentials_key(&self) -> &'static str {
+        let totp = self.methods.is_empty()
+            || self
+                .methods
+                .iter()
+                .any(|method| method.method_type == "totp");
+
+        match (self.key.as_str(), totp) {
+            ("okta_verify", true) => "totp",
+            _ => "passcode",
+        }
+    }
+}
+
+/// An authenticator and method the user could verify with
+struct IdxCandidate {
+    id: String,
+    method_type: String,
+    kind: Option<FactorType>,
+}
+
+fn factor_type(method_type: &str) -> Option<FactorType> {
+    match method_type {
+        "totp" | "otp" => Some(FactorType::Totp),
+        "push" => Some(FactorType::Push),
+        "sms" => Some(FactorType::Sms),
+        "voice" => Some(FactorType::Call),
+        "email" => Some(FactorType::Email),
+        _ => None,
+    }
+}
+
+impl OktaClient {
+    /// Sign in with IDX remediations, then exchange the interaction code for tokens
+    pub async fn get_tokens_with_idx(&self) -> Result<TokenResponse, OktaClientError> {
+        let interaction_handle = self.do_idx_interact().await?;
+
+        let mut response = self
+            .post_idx(
+                &self.okta_url("idp/idx/introspect"),
+                &json!({ "interactionHandle": interaction_handle }),
+            )
+            .await?;
+
+        // The remediation expected after the last step, ie. challenge-poll after sending a push
+        let mut expected = None;
+
+        for _ in 0..MAX_REMEDIATIONS {
+            if let Some(success) = &response.success_with_interaction_code {
+                let interaction_code = success
+                    .field("interaction_code")
+                    .and_then(|field| field.value.as_str())
+                    .ok_or_else(|| {
+                        OktaClientError::Parser(
+                            "IDX success response was missing interaction_code".to_owned(),
+                        )
+                    })?;
+
+                return self
+                    .do_oauth_interaction_code(interaction_code.to_owned())
+                    .await;
+            }
+
+            let (next, next_expected) = self.do_idx_remediation(response, expected).await?;
+            response = next;
+            expected = next_expected;
+        }
+
+        Err(OktaClientError::Idx(
+            "Signing in took too many steps".to_owned(),
+        ))
+    }
+
+    /// Start an IDX transaction, with the same PKCE verifier used to exchange the interaction code
+    async fn do_idx_interact(&self) -> Result<String, OktaClientError> {
+        let url = format!(
+            "{}/interact",
+            self.openid_config.token_endpoint.trim_end_matches("/token")
+        );
+
+        let request = IdxInteractRequest {
+            client_id: self.client_id.to_owned(),
+            scope: self.scopes.to_owned(),
+            code_challenge: self.pkce.code_challenge.to_owned(),
+            code_challenge_method: "S256".to_owned(),
+            redirect_uri: self.login_redirect_url()?.to_owned(),
+            state: random_string(),
+            client_authentication: self
+                .client_authentication
+                .as_params(&self.client_id, &url)?,
+        };
+
+        let response = self
+            .http
+            .post(&url)
+            .form(&request)
+            .send()
+            .await?
+            .json::<IdxInteractResponse>()
+            .await?;
+
+        match response {
+            IdxInteractResponse {
+                interaction_handle: Some(interaction_handle),
+                ..
+            } => Ok(interaction_handle),
+            IdxInteractResponse {
+                error: Some(error),
+                error_description,
+                ..
+            } => Err(OktaClientError::OktaAPI(format!(
+                "{} {}",
+                error,
+                error_description.unwrap_or_default()
+            ))),
+            _ => Err(OktaClientError::Parser(
+                "Interact response was missing interaction_handle".to_owned(),
+            )),
+        }
+    }
+
+    /// Take the next step to sign in, the expected remediation if it is offered, and return the
+    /// remediation expected after it
+    async fn do_idx_remediation(
+        &self,
+        response: IdxResponse,
+        expected: Option<&'static str>,
+    ) -> Result<(IdxResponse, Option<&'static str>), OktaClientError> {
+        let state_handle = response.state_handle.to_owned().ok_or_else(|| {
+            OktaClientError::Parser("IDX response was missing stateHandle".to_owned())
+        })?;
+
+        let remediation = |name: &str| {
+            response
+                .remediation
+                .value
+                .iter()
+                .find(|remediation| remediation.name == name)
+        };
+
+        let next = expected
+            .into_iter()
+            .chain(REMEDIATIONS)
+            .find_map(remediation);
+
+        if let Some(next) = next {
+            return match next.name.as_str() {
+                "identify" => {
+                    let (username, password) = self.credentials()?;
+
+                    let mut request =
+                        json!({ "identifier": username, "stateHandle": state_handle });
+
+                    // Some policies ask for the password with the username
+                    if next.field("credentials").is_some() {
+                        request["credentials"] = json!({ "passcode": password });
+                    }
+
+                    Ok((self.post_idx(&next.href, &request).await?, None))
+                }
+                "challenge-poll" => {
+                    Ok((self.do_idx_poll(&response, next, state_handle).await?, None))
+                }
+                "challenge-authenticator" => Ok((
+                    self.do_idx_challenge(&response, next, state_handle).await?,
+                    None,
+                )),
+                _ => self.do_idx_select(&response, next, state_handle).await,
+            };
+        }
+
+        let names = response
+            .remediation
+            .value
+            .iter()
+            .map(|remediation| remediation.name.as_str())
+            .collect::<Vec<_>>();
+
+        if names
+            .iter()
+            .any(|name| name.starts_with("enroll") || name.contains("-enroll"))
+        {
+            return Err(OktaClientError::MfaEnroll(
+                "An authenticator must be enrolled before signing in".to_owned(),
+            ));
+        }
+
+        if names.contains(&"redirect-idp") {
+            return Err(OktaClientError::Idx(
+                "The user signs in with an external identity provider".to_owned(),
+            ));
+        }
+
+        Err(OktaClientError::Idx(format!(
+            "No supported remediation was offered: {}",
+            names.join(", ")
+        )))
+    }
+
+    /// Choose an authenticator, the password if it is offered, otherwise the chosen or preferred factor
+    /// Returns the remediation expected next, challenge-poll for a push
+    async fn do_idx_select(
+        &self,
+        response: &IdxResponse,
+        select: &IdxRemediation,
+        state_handle: String,
+    ) -> Result<(IdxResponse, Option<&'static str>), OktaClientError> {
+        let candidates = select
+            .field("authenticator")
+            .map(|field| {
+                field
+                    .options
+                    .iter()
+                    .flat_map(|option| {
+                        let fields = IdxField::option_fields(option);
+                        let id = fields
+                            .iter()
+                            .find(|field| field.name == "id")
+                            .and_then(|field| field.value.as_str())
+                            .unwrap_or_default()
+                            .to_owned();
+
+                        fields
+                            .iter()
+                            .find(|field| field.name == "methodType")
+                            .map(|field| field.values())
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(move |method_type| IdxCandidate {
+                                id: id.to_owned(),
+                                kind: factor_type(&method_type),
+                                method_type,
+                            })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let offered = || {
+            candidates
+                .iter()
+                .map(|c| c.method_type.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let candidate = match (
+            candidates.iter().find(|c| c.method_type == "password"),
+            self.factor,
+        ) {
+            (Some(password), _) => password,
+            (None, Some(kind)) => candidates
+                .iter()
+                .find(|c| c.kind == Some(kind))
+                .ok_or_else(|| {
+                    OktaClientError::Mfa(format!(
+                        "No {} authenticator was offered, the offered methods are: {}",
+                        kind,
+                        offered()
+                    ))
+                })?,
+            (None, None) => FactorType::PREFERENCE
+                .iter()
+                .find_map(|kind| candidates.iter().find(|c| c.kind == Some(*kind)))
+                .ok_or_else(|| {
+                    OktaClientError::Mfa(format!(
+                        "None of the offered authenticators are supported: {}",
+                        offered()
+                    ))
+                })?,
+        };
+
+        let request = json!({
+            "authenticator": { "id": candidate.id, "methodType": candidate.method_type },
+            "stateHandle": state_handle,
+        });
+
+        let next = self.post_idx(&select.href, &request).await?;
+
+        if candidate.kind == Some(FactorType::Push) {
+            let authenticator = response
+                .authenticators
+                .value
+                .iter()
+                .find(|authenticator| authenticator.id == candidate.id)
+                .cloned()
+                .unwrap_or_default();
+
+            self.push_status(
+                &authenticator.as_factor(FactorType::Push),
+                PushStatus::Sent {
+                    correct_answer: next
+                        .current_authenticator
+                        .as_ref()
+                        .and_then(|current| current.value.contextual_data.correct_answer),
+                },
+            );
+
+            return Ok((next, Some("challenge-poll")));
+        }
+
+        Ok((next, Some("challenge-authenticator")))
+    }
+
+    /// Answer the current authenticator with the password, a passcode or a code that was sent
+    async fn do_idx_challenge(
+        &self,
+        response: &IdxResponse,
+        challenge: &IdxRemediation,
+        state_handle: String,
+    ) -> Result<IdxResponse, OktaClientError> {
+        let authenticator = response
+            .current_authenticator_enrollment
+            .as_ref()
+            .map(|enrollment| enrollment.value.to_owned())
+            .unwrap_or_default();
+
+        let passcode = match authenticator.authenticator_type.as_str() {
+            "password" => self.credentials()?.1.to_owned(),
+            "email" | "phone_number" => {
+                let kind = match (authenticator.authenticator_type.as_str(), self.factor) {
+                    ("email", _) => FactorType::Email,
+                    (_, Some(FactorType::Call)) => FactorType::Call,
+                    _ => FactorType::Sms,
+                };
+                let factor = authenticator.as_factor(kind);
+                let prompt = self.required_prompt(&factor)?;
+
+                loop {
+                    match prompt.challenge(&factor)? {
+                        ChallengeAnswer::Passcode(passcode) => break passcode,
+                        ChallengeAnswer::Resend => {
+                            let resend = authenticator.resend.as_ref().ok_or_else(|| {
+                                OktaClientError::Mfa(format!(
+                                    "The code for {} can't be resent",
+                                    factor.description()
+                                ))
+                            })?;
+
+                            self.post_idx(&resend.href, &json!({ "stateHandle": state_handle }))
+                                .await?;
+                        }
+                    }
+                }
+            }
+            _ => {
+                let factor = authenticator.as_factor(FactorType::Totp);
+
+                match &self.totp {
+                    Some(totp) => totp.now(),
+                    None => self.required_prompt(&factor)?.passcode(&factor)?,
+                }
+            }
+        };
+
+        let request = json!({
+            "credentials": { authenticator.credentials_key(): passcode },
+            "stateHandle": state_handle,
+        });
+
+        self.post_idx(&challenge.href, &request).await
+    }
+
+    /// Poll until a push is approved
+    async fn do_idx_poll(
+        &self,
+        response: &IdxResponse,
+        poll: &IdxRemediation,
+        state_handle: String,
+    ) -> Result<IdxResponse, OktaClientError> {
+        let factor = response
+            .current_authenticator
+            .as_ref()
+            .map(|current| current.value.to_owned())
+            .unwrap_or_default()
+            .as_factor(FactorType::Push);
+
+        let started = Instant::now();
+        let mut poll = poll.to_owned();
+
+        loop {
+            if started.elapsed() > PUSH_TIMEOUT {
+                self.push_status(&factor, PushStatus::TimedOut);
+                return Err(OktaClientError::Mfa(
+                    "Gave up waiting for the push to be approved".to_owned(),
+                ));
+            }
+
+            self.push_status(&factor, PushStatus::Waiting);
+
+            tokio::time::sleep(
+                poll.refresh
+                    .map(Duration::from_millis)
+                    .unwrap_or(DEFAULT_POLL_INTERVAL),
+            )
+            .await;
+
+            let next = self
+                .post_idx(&poll.href, &json!({ "stateHandle": state_handle }))
+                .await?;
+
+            match next
+                .remediation
+                .value
+                .iter()
+                .find(|remediation| remediation.name == "challenge-poll")
+            {
+                Some(next_poll) => poll = next_poll.to_owned(),
+                None => {
+                    self.push_status(&factor, PushStatus::Approved);
+                    return Ok(next);
+                }
+            }
+        }
+    }
+
+    /// Post to an /idp/idx endpoint, returning the error messages if there are any
+    async fn post_idx(&self, url: &str, request: &Value) -> Result<IdxResponse, OktaClientError> {
+        let response = self
+            .http
+            .post(url)
+            .header(reqwest::header::ACCEPT, IDX_CONTENT_TYPE)
+            .header(reqwest::header::CONTENT_TYPE, IDX_CONTENT_TYPE)
+            .body(serde_json::to_vec(request)?)
+            .send()
+            .await?
+            .text()
+            .await?;
+
+        let response = serde_json::from_str::<IdxResponse>(&response)
+            .map_err(|_| OktaClientError::from_okta_api_error(&response))
+            .map_err(|e| e.unwrap_or_else(|| OktaClientError::Parser(response.to_owned())))?;
+
+        let errors = response
+            .messages
+            .value
+            .iter()
+            .filter(|message| message.class == "ERROR")
+            .map(|message| message.message.as_str())
+            .collect::<Vec<_>>();
+
+        if !errors.is_empty() {
+            return Err(OktaClientError::OktaAPI(errors.join(" ")));
+        }
+
+        Ok(response)
+    }
+}
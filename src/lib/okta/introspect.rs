@@ -0,0 +1,57 @@
+/// Ask the authorization server whether a token is still active (RFC 7662)
+use crate::okta::revoke::endpoint_error;
+use crate::okta::{Claims, OktaClient, OktaClientError, TokenTypeHint};
+
+use serde::{Deserialize, Serialize};
+
+/// The introspection endpoint's view of a token, only active is returned for inactive tokens
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TokenIntrospection {
+    /// False if the token has expired, been revoked or wasn't issued by this authorization server
+    pub active: bool,
+    /// When the token expires, as a unix timestamp
+    pub exp: Option<i64>,
+    pub scope: Option<String>,
+    pub sub: Option<String>,
+    pub client_id: Option<String>,
+    /// The other claims, ie. username, token_type or uid
+    #[serde(flatten)]
+    pub claims: Claims,
+}
+
+impl OktaClient {
+    /// Check whether an access or refresh token is active on the authorization server,
+    /// unlike verify_access_token this sees tokens that were revoked before they expired
+    pub async fn introspect(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+    ) -> Result<TokenIntrospection, OktaClientError> {
+        let endpoint = self
+            .openid_config
+            .introspection_endpoint
+            .as_deref()
+            .ok_or_else(|| {
+                OktaClientError::Configuration(
+                    "The authorization server doesn't publish an introspection_endpoint".to_owned(),
+                )
+            })?;
+
+        let response = self
+            .http
+            .post(endpoint)
+            .form(&self.token_hint_request(token, token_type_hint, endpoint)?)
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        if !status.is_success() {
+            return Err(endpoint_error(status, &text, "introspecting"));
+        }
+
+        serde_json::from_str::<TokenIntrospection>(&text).map_err(|e| {
+            OktaClientError::Parser(format!("Invalid introspection response ({}): {}", e, text))
+        })
+    }
+}
@@ -0,0 +1,151 @@
+/// Verify ID and access tokens against the JSON Web Key Set (JWKS) of the authorization server
+use crate::okta::{OktaClient, OktaClientError};
+
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// The signing algorithms that can be verified, OKTA uses RS256
+const SUPPORTED_ALGORITHMS: [Algorithm; 2] = [Algorithm::RS256, Algorithm::ES256];
+
+/// The claims of a verified token, ie. sub, email or scp
+pub type Claims = HashMap<String, serde_json::Value>;
+
+/// The keys from jwks_uri by key ID, fetched again when a token is signed with an unknown key
+#[derive(Default)]
+pub(crate) struct KeyCache {
+    keys: Mutex<HashMap<String, Jwk>>,
+}
+
+impl KeyCache {
+    fn get(&self, kid: &str) -> Option<Jwk> {
+        self.keys.lock().ok()?.get(kid).cloned()
+    }
+
+    fn replace(&self, jwk_set: JwkSet) {
+        if let Ok(mut keys) = self.keys.lock() {
+            *keys = jwk_set
+                .keys
+                .into_iter()
+                .filter_map(|jwk| Some((jwk.common.key_id.to_owned()?, jwk)))
+                .collect();
+        }
+    }
+}
+
+impl OktaClient {
+    /// Verify the signature, issuer, audience (the client ID), expiry and not before time of an
+    /// ID token, and the nonce if one was sent to the authorize endpoint
+    pub async fn verify_id_token(
+        &self,
+        id_token: &str,
+        nonce: Option<&str>,
+    ) -> Result<Claims, OktaClientError> {
+        let claims = self.verify_token(id_token, Some(&self.client_id)).await?;
+
+        if let Some(nonce) = nonce {
+            if claims.get("nonce").and_then(|value| value.as_str()) != Some(nonce) {
+                return Err(OktaClientError::NonceMismatch(
+                    "The nonce in the ID token didn't match the one sent".to_owned(),
+                ));
+            }
+        }
+
+        Ok(claims)
+    }
+
+    /// Verify the signature, issuer, expiry and not before time of an access token, and the
+    /// audience if given, ie. api://default
+    /// Only access tokens from a custom authorization server can be verified, the org
+    /// authorization server's are only for OKTA
+    pub async fn verify_access_token(
+        &self,
+        access_token: &str,
+        audience: Option<&str>,
+    ) -> Result<Claims, OktaClientError> {
+        self.verify_token(access_token, audience).await
+    }
+
+    async fn verify_token(
+        &self,
+        token: &str,
+        audience: Option<&str>,
+    ) -> Result<Claims, OktaClientError> {
+        let header = jsonwebtoken::decode_header(token)
+            .map_err(|e| OktaClientError::InvalidToken(e.to_string()))?;
+
+        if !SUPPORTED_ALGORITHMS.contains(&header.alg) {
+            return Err(OktaClientError::InvalidToken(format!(
+                "Tokens signed with {:?} aren't supported",
+                header.alg
+            )));
+        }
+
+        let kid = header.kid.ok_or_else(|| {
+            OktaClientError::InvalidToken("The token has no key ID (kid)".to_owned())
+        })?;
+        let key = self.signing_key(&kid).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.validate_nbf = true;
+        validation.set_issuer(&[&self.openid_config.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        if let Some(audience) = audience {
+            validation.set_audience(&[audience]);
+        }
+
+        jsonwebtoken::decode::<Claims>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(invalid_token)
+    }
+
+    /// The key a token was signed with, fetching the keys again if it isn't cached,
+    /// ie. after OKTA rotates its keys
+    async fn signing_key(&self, kid: &str) -> Result<DecodingKey, OktaClientError> {
+        let jwk = match self.jwks.get(kid) {
+            Some(jwk) => jwk,
+            None => {
+                let jwks_uri = self.openid_config.jwks_uri.as_deref().ok_or_else(|| {
+                    OktaClientError::Configuration(
+                        "The authorization server doesn't publish a jwks_uri".to_owned(),
+                    )
+                })?;
+
+                let text = self.http.get(jwks_uri).send().await?.text().await?;
+                let jwk_set = serde_json::from_str::<JwkSet>(&text).map_err(|e| {
+                    OktaClientError::Parser(format!(
+                        "Invalid JWKS at {} ({}): {}",
+                        jwks_uri, e, text
+                    ))
+                })?;
+                self.jwks.replace(jwk_set);
+
+                self.jwks.get(kid).ok_or_else(|| {
+                    OktaClientError::InvalidToken(format!(
+                        "The key {} isn't in the JWKS at {}",
+                        kid, jwks_uri
+                    ))
+                })?
+            }
+        };
+
+        DecodingKey::from_jwk(&jwk).map_err(|e| OktaClientError::InvalidToken(e.to_string()))
+    }
+}
+
+fn invalid_token(error: jsonwebtoken::errors::Error) -> OktaClientError {
+    use jsonwebtoken::errors::ErrorKind;
+
+    OktaClientError::InvalidToken(match error.kind() {
+        ErrorKind::InvalidSignature => "The signature doesn't match".to_owned(),
+        ErrorKind::ExpiredSignature => "The token has expired".to_owned(),
+        ErrorKind::ImmatureSignature => "The token isn't valid yet (nbf)".to_owned(),
+        ErrorKind::InvalidIssuer => {
+            "The token was issued by another authorization server".to_owned()
+        }
+        ErrorKind::InvalidAudience => "The token is for another audience".to_owned(),
+        ErrorKind::MissingRequiredClaim(claim) => format!("The token has no {} claim", claim),
+        _ => error.to_string(),
+    })
+}
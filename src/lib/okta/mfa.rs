@@ -0,0 +1,413 @@
+/// Verify a factor when the OKTA /api/v1/authn endpoint returns MFA_REQUIRED
+use crate::okta::authn::{AuthnStatus, OktaAuthnResponse, OktaLink};
+use crate::okta::{OktaClient, OktaClientError};
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How often to poll for the result of a push
+const PUSH_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Give up on a push if OKTA hasn't timed it out already
+pub(crate) const PUSH_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A factor the user has enrolled
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OktaFactor {
+    pub id: String,
+    /// ie. token:software:totp, push, sms, call or email
+    pub factor_type: String,
+    /// ie. OKTA or GOOGLE
+    pub provider: String,
+    pub profile: OktaFactorProfile,
+    #[serde(rename = "_links")]
+    pub links: OktaFactorLinks,
+    #[serde(rename = "_embedded")]
+    pub embedded: OktaFactorEmbedded,
+}
+
+/// Where a code is sent, for SMS, call and email factors
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OktaFactorProfile {
+    pub phone_number: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct OktaFactorLinks {
+    pub verify: Option<OktaLink>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct OktaFactorEmbedded {
+    pub challenge: Option<OktaChallenge>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OktaChallenge {
+    /// The number to pick in Okta Verify, if number challenge is enabled
+    pub correct_answer: Option<u32>,
+}
+
+/// The kinds of factor that can be verified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FactorType {
+    /// A passcode from Google Authenticator or Okta Verify
+    Totp,
+    /// A push to Okta Verify
+    Push,
+    /// A code sent by SMS
+    Sms,
+    /// A code read out in a voice call
+    Call,
+    /// A code sent by email
+    Email,
+}
+
+impl FactorType {
+    pub const NAMES: [&'static str; 5] = ["totp", "push", "sms", "call", "email"];
+
+    /// The order factors are picked in, when one isn't chosen
+    pub(crate) const PREFERENCE: [FactorType; 5] = [
+        FactorType::Totp,
+        FactorType::Push,
+        FactorType::Sms,
+        FactorType::Call,
+        FactorType::Email,
+    ];
+}
+
+impl std::fmt::Display for FactorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FactorType::Totp => f.write_str("totp"),
+            FactorType::Push => f.write_str("push"),
+            FactorType::Sms => f.write_str("sms"),
+            FactorType::Call => f.write_str("call"),
+            FactorType::Email => f.write_str("email"),
+        }
+    }
+}
+
+impl std::str::FromStr for FactorType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "totp" => Ok(FactorType::Totp),
+            "push" => Ok(FactorType::Push),
+            "sms" => Ok(FactorType::Sms),
+            "call" => Ok(FactorType::Call),
+            "email" => Ok(FactorType::Email),
+            _ => Err(format!("Unknown factor {}", s)),
+        }
+    }
+}
+
+impl OktaFactor {
+    /// The kind of factor, if it is supported
+    pub fn kind(&self) -> Option<FactorType> {
+        match self.factor_type.as_str() {
+            "token:software:totp" => Some(FactorType::Totp),
+            "push" => Some(FactorType::Push),
+            "sms" => Some(FactorType::Sms),
+            "call" => Some(FactorType::Call),
+            "email" => Some(FactorType::Email),
+            _ => None,
+        }
+    }
+
+    pub fn is_totp(&self) -> bool {
+        self.kind() == Some(FactorType::Totp)
+    }
+
+    pub fn is_push(&self) -> bool {
+        self.kind() == Some(FactorType::Push)
+    }
+
+    /// A name for the factor to show the user
+    pub fn description(&self) -> String {
+        let to = |destination: &Option<String>| {
+            destination
+                .as_ref()
+                .map(|destination| format!(" to {}", destination))
+                .unwrap_or_default()
+        };
+
+        match (self.factor_type.as_str(), self.provider.as_str()) {
+            ("token:software:totp", "GOOGLE") => "Google Authenticator".to_owned(),
+            ("token:software:totp", "OKTA") => "Okta Verify".to_owned(),
+            ("push", _) => "Okta Verify Push".to_owned(),
+            ("sms", _) => format!("SMS{}", to(&self.profile.phone_number)),
+            ("call", _) => format!("Voice Call{}", to(&self.profile.phone_number)),
+            ("email", _) => format!("Email{}", to(&self.profile.email)),
+            (factor_type, provider) => format!("{} ({})", factor_type, provider),
+        }
+    }
+}
+
+/// The answer to a code sent by SMS, voice call or email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChallengeAnswer {
+    Passcode(String),
+    /// Send another code, ie. if the first didn't arrive
+    Resend,
+}
+
+/// The progress of a push to Okta Verify
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PushStatus {
+    /// The push was sent, with the number to pick if number challenge is enabled
+    Sent {
+        correct_answer: Option<u32>,
+    },
+    /// Still waiting for the user to respond
+    Waiting,
+    Approved,
+    Rejected,
+    TimedOut,
+}
+
+/// Asks the user for the passcode of a factor and reports progress, ie. on the CLI
+pub trait MfaPrompt: Send + Sync {
+    /// The passcode currently shown by the authenticator app for this factor
+    fn passcode(&self, factor: &OktaFactor) -> Result<String, OktaClientError>;
+
+    /// The code sent by SMS, voice call or email, defaults to asking for a passcode
+    fn challenge(&self, factor: &OktaFactor) -> Result<ChallengeAnswer, OktaClientError> {
+        self.passcode(factor).map(ChallengeAnswer::Passcode)
+    }
+
+    /// Called as a push is sent and polled
+    fn push_status(&self, _factor: &OktaFactor, _status: &PushStatus) {}
+}
+
+/// A passcode that is already known, ie. passed as a flag
+pub struct Passcode(pub String);
+
+impl MfaPrompt for Passcode {
+    fn passcode(&self, _factor: &OktaFactor) -> Result<String, OktaClientError> {
+        Ok(self.0.to_owned())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OktaVerifyRequest {
+    pub state_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pass_code: Option<String>,
+}
+
+impl OktaClient {
+    /// Verify one of the user's enrolled factors to get a session token
+    pub async fn do_okta_mfa(
+        &self,
+        authn: OktaAuthnResponse,
+    ) -> Result<OktaAuthnResponse, OktaClientError> {
+        let state_token = authn.state_token.ok_or_else(|| {
+            OktaClientError::Parser("MFA_REQUIRED response was missing stateToken".to_owned())
+        })?;
+
+        let factor = self.select_factor(&authn.embedded.factors)?;
+
+        match factor.kind() {
+            Some(FactorType::Push) => self.verify_push(state_token, factor).await,
+            Some(FactorType::Sms) | Some(FactorType::Call) | Some(FactorType::Email) => {
+                self.verify_challenge(state_token, factor).await
+            }
+            _ => self.verify_passcode(state_token, factor).await,
+        }
+    }
+
+    /// The chosen factor, otherwise the first supported factor in order of preference
+    fn select_factor<'f>(
+        &self,
+        factors: &'f [OktaFactor],
+    ) -> Result<&'f OktaFactor, OktaClientError> {
+        let enrolled = || {
+            factors
+                .iter()
+                .map(|factor| factor.description())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        match self.factor {
+            Some(kind) => factors
+                .iter()
+                .find(|factor| factor.kind() == Some(kind))
+                .ok_or_else(|| {
+                    OktaClientError::Mfa(format!(
+                        "No {} factor is enrolled, the enrolled factors are: {}",
+                        kind,
+                        enrolled()
+                    ))
+                }),
+            None => FactorType::PREFERENCE
+                .iter()
+                .find_map(|kind| factors.iter().find(|factor| factor.kind() == Some(*kind)))
+                .ok_or_else(|| {
+                    OktaClientError::Mfa(format!(
+                        "None of the enrolled factors are supported: {}",
+                        enrolled()
+                    ))
+                }),
+        }
+    }
+
+    pub(crate) fn required_prompt(
+        &self,
+        factor: &OktaFactor,
+    ) -> Result<&dyn MfaPrompt, OktaClientError> {
+        self.mfa_prompt.as_deref().ok_or_else(|| {
+            OktaClientError::Mfa(format!(
+                "A passcode for {} is required, but no MFA prompt was configured",
+                factor.description()
+            ))
+        })
+    }
+
+    async fn verify_passcode(
+        &self,
+        state_token: String,
+        factor: &OktaFactor,
+    ) -> Result<OktaAuthnResponse, OktaClientError> {
+        let pass_code = match &self.totp {
+            Some(totp) if factor.is_totp() => totp.now(),
+            _ => self.required_prompt(factor)?.passcode(factor)?,
+        };
+
+        let request = OktaVerifyRequest {
+            state_token,
+            pass_code: Some(pass_code),
+        };
+
+        self.post_authn(&self.verify_url(factor), &request).await
+    }
+
+    /// Send a code by SMS, voice call or email, then verify it, resending it if asked
+    async fn verify_challenge(
+        &self,
+        state_token: String,
+        factor: &OktaFactor,
+    ) -> Result<OktaAuthnResponse, OktaClientError> {
+        let prompt = self.required_prompt(factor)?;
+
+        let request = OktaVerifyRequest {
+            state_token,
+            pass_code: None,
+        };
+
+        let mut challenge = self.post_authn(&self.verify_url(factor), &request).await?;
+
+        loop {
+            match prompt.challenge(factor)? {
+                ChallengeAnswer::Passcode(pass_code) => {
+                    let url = match &challenge.links.next {
+                        Some(next) => next.href.to_owned(),
+                        None => self.verify_url(factor),
+                    };
+
+                    let request = OktaVerifyRequest {
+                        state_token: request.state_token,
+                        pass_code: Some(pass_code),
+                    };
+
+                    return self.post_authn(&url, &request).await;
+                }
+                ChallengeAnswer::Resend => {
+                    let resend = challenge.links.resend.first().ok_or_else(|| {
+                        OktaClientError::Mfa(format!(
+                            "The code for {} can't be resent",
+                            factor.description()
+                        ))
+                    })?;
+
+                    challenge = self.post_authn(&resend.href, &request).await?;
+                }
+            }
+        }
+    }
+
+    /// Send a push to Okta Verify, then poll until it is approved, rejected or times out
+    async fn verify_push(
+        &self,
+        state_token: String,
+        factor: &OktaFactor,
+    ) -> Result<OktaAuthnResponse, OktaClientError> {
+        let request = OktaVerifyRequest {
+            state_token,
+            pass_code: None,
+        };
+
+        let mut response = self.post_authn(&self.verify_url(factor), &request).await?;
+
+        self.push_status(
+            factor,
+            PushStatus::Sent {
+                correct_answer: response
+                    .embedded
+                    .factor
+                    .as_ref()
+                    .and_then(|factor| factor.embedded.challenge.as_ref())
+                    .and_then(|challenge| challenge.correct_answer),
+            },
+        );
+
+        let started = Instant::now();
+
+        loop {
+            match (response.status, response.factor_result.as_deref()) {
+                (Some(AuthnStatus::MfaChallenge), Some("REJECTED")) => {
+                    self.push_status(factor, PushStatus::Rejected);
+                    return Err(OktaClientError::Mfa("The push was rejected".to_owned()));
+                }
+                (Some(AuthnStatus::MfaChallenge), Some("TIMEOUT")) => {
+                    self.push_status(factor, PushStatus::TimedOut);
+                    return Err(OktaClientError::Mfa("The push timed out".to_owned()));
+                }
+                (Some(AuthnStatus::MfaChallenge), _) if started.elapsed() > PUSH_TIMEOUT => {
+                    self.push_status(factor, PushStatus::TimedOut);
+                    return Err(OktaClientError::Mfa(
+                        "Gave up waiting for the push to be approved".to_owned(),
+                    ));
+                }
+                (Some(AuthnStatus::MfaChallenge), _) => {
+                    self.push_status(factor, PushStatus::Waiting)
+                }
+                // Approved, the transaction continues from its next status
+                _ => {
+                    self.push_status(factor, PushStatus::Approved);
+                    return Ok(response);
+                }
+            }
+
+            let next = response.links.next.ok_or_else(|| {
+                OktaClientError::Parser("MFA_CHALLENGE response was missing a next link".to_owned())
+            })?;
+
+            tokio::time::sleep(PUSH_POLL_INTERVAL).await;
+
+            response = self.post_authn(&next.href, &request).await?;
+        }
+    }
+
+    pub(crate) fn push_status(&self, factor: &OktaFactor, status: PushStatus) {
+        if let Some(prompt) = &self.mfa_prompt {
+            prompt.push_status(factor, &status);
+        }
+    }
+
+    fn verify_url(&self, factor: &OktaFactor) -> String {
+        match &factor.links.verify {
+            Some(link) => link.href.to_owned(),
+            None => self.okta_url(&format!("api/v1/authn/factors/{}/verify", factor.id)),
+        }
+    }
+}
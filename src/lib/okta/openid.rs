@@ -1,71 +1,215 @@
-use crate::okta::OktaClientError;
+/// Discover the endpoints and capabilities of an authorization server
+use crate::okta::{Flow, OktaClientError, ResponseMode, ResponseType};
 
 use scraper::{Html, Selector};
-use std::collections::HashMap;
+use serde::Deserialize;
 
+/// The scopes defined by OpenID Connect, which OKTA always publishes when they are supported
+const STANDARD_SCOPES: [&str; 6] = [
+    "openid",
+    "profile",
+    "email",
+    "address",
+    "phone",
+    "offline_access",
+];
+
+/// The OpenID discovery document of an authorization server, from .well-known/openid-configuration
+#[derive(Deserialize, Debug, Clone)]
 pub struct OpenIDConfig {
+    pub issuer: String,
     pub authorization_endpoint: String,
     pub token_endpoint: String,
+    /// The keys tokens are signed with
+    pub jwks_uri: Option<String>,
+    pub userinfo_endpoint: Option<String>,
+    pub introspection_endpoint: Option<String>,
+    pub revocation_endpoint: Option<String>,
+    pub end_session_endpoint: Option<String>,
+    /// Only advertised if the authorization server allows the Device Authorization grant
+    pub device_authorization_endpoint: Option<String>,
+    /// Only the scopes the authorization server publishes, custom scopes can be left out
+    #[serde(default)]
+    pub scopes_supported: Vec<String>,
+    #[serde(default)]
+    pub response_types_supported: Vec<String>,
+    #[serde(default)]
+    pub response_modes_supported: Vec<String>,
+    #[serde(default)]
+    pub grant_types_supported: Vec<String>,
+    #[serde(default)]
+    pub code_challenge_methods_supported: Vec<String>,
+}
+
+impl OpenIDConfig {
+    /// Check the standard scopes requested are supported
+    /// Custom scopes aren't checked, as OKTA only publishes those set to be shown in the metadata
+    pub fn validate_scopes(&self, scopes: &[String]) -> Result<(), OktaClientError> {
+        match scopes.iter().find(|scope| {
+            STANDARD_SCOPES.contains(&scope.as_str()) && !supports(&self.scopes_supported, scope)
+        }) {
+            Some(scope) => Err(OktaClientError::Configuration(format!(
+                "The authorization server doesn't support the {} scope",
+                scope
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the grant type, response type and response mode a flow uses are supported
+    pub fn validate_flow(
+        &self,
+        flow: Flow,
+        response_type: ResponseType,
+        response_mode: ResponseMode,
+    ) -> Result<(), OktaClientError> {
+        let grant_type = match (flow, response_type) {
+            (Flow::Authn, ResponseType::Token) | (Flow::Authn, ResponseType::IdToken) => "implicit",
+            (Flow::Authn, ResponseType::Code) | (Flow::Browser, _) => "authorization_code",
+            (Flow::Password, _) => "password",
+            (Flow::Idx, _) => "interaction_code",
+        };
+        self.validate_grant_type(grant_type)?;
+
+        // Only Flow::Authn uses the response type and mode that were configured
+        let (response_type, response_mode) = match flow {
+            Flow::Authn => (response_type, response_mode),
+            Flow::Browser => (ResponseType::Code, ResponseMode::FormPost),
+            Flow::Password | Flow::Idx => return Ok(()),
+        };
+
+        if !supports(&self.response_types_supported, &response_type.to_string()) {
+            return Err(OktaClientError::Configuration(format!(
+                "The authorization server doesn't support the {} response type",
+                response_type
+            )));
+        }
+
+        if !supports(&self.response_modes_supported, &response_mode.to_string()) {
+            return Err(OktaClientError::Configuration(format!(
+                "The authorization server doesn't support the {} response mode",
+                response_mode
+            )));
+        }
+
+        if response_type == ResponseType::Code
+            && !supports(&self.code_challenge_methods_supported, "S256")
+        {
+            return Err(OktaClientError::Configuration(
+                "The authorization server doesn't support PKCE with S256".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check a grant type is supported, ie. client_credentials
+    pub fn validate_grant_type(&self, grant_type: &str) -> Result<(), OktaClientError> {
+        match supports(&self.grant_types_supported, grant_type) {
+            true => Ok(()),
+            false => Err(OktaClientError::Configuration(format!(
+                "The authorization server doesn't support the {} grant",
+                grant_type
+            ))),
+        }
+    }
+}
+
+/// Whether a value is in a list of supported values, which is all of them if it wasn't published
+fn supports(supported: &[String], value: &str) -> bool {
+    supported.is_empty() || supported.iter().any(|supported| supported == value)
 }
 
-/// Get OpenID config from .well-known
-#[tokio::main]
+/// Get the discovery document of an issuer from .well-known/openid-configuration, falling back
+/// to the OAuth metadata (RFC 8414) of authorization servers that aren't OpenID providers
 pub async fn get_openid_config(
-    base_url: String,
-    authorization_server_id: String,
+    client: &reqwest::Client,
+    issuer: &reqwest::Url,
+) -> Result<OpenIDConfig, OktaClientError> {
+    let mut urls = vec![
+        issuer.join(".well-known/openid-configuration"),
+        issuer.join(".well-known/oauth-authorization-server"),
+    ];
+
+    // RFC 8414 puts the well-known path before the issuer's path
+    if issuer.path() != "/" {
+        urls.push(issuer.join(&format!(
+            "/.well-known/oauth-authorization-server{}",
+            issuer.path().trim_end_matches('/')
+        )));
+    }
+
+    let mut first_error = None;
+    for url in urls {
+        let url = url.map_err(|e| OktaClientError::InvalidUrl(format!("{}: {}", issuer, e)))?;
+
+        match get_metadata(client, url.as_str()).await {
+            Ok(config) => return Ok(config),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    // Report why the OpenID configuration couldn't be read, it's the one OKTA publishes
+    Err(first_error.unwrap_or_else(|| {
+        OktaClientError::General(format!("No metadata found for the issuer {}", issuer))
+    }))
+}
+
+async fn get_metadata(
+    client: &reqwest::Client,
+    url: &str,
 ) -> Result<OpenIDConfig, OktaClientError> {
-    let url = format!(
-        "{}/oauth2/{}/.well-known/openid-configuration",
-        base_url, authorization_server_id
-    );
+    let response = client.get(url).send().await?;
+    let text = response.text().await?;
 
-    let response = reqwest::get(&url).await.expect("Error Getting URL");
-    let text = response.text().await.expect("Error getting text body");
-    let json = serde_json::from_str::<HashMap<String, serde_json::Value>>(&text);
+    // Look for a JSON error in this format:
+    // {"errorCode":"E0000007","errorSummary":"Not found: Resource not found: ABCXYZ (AuthorizationServer)","errorLink":"E0000007","errorId":"123123","errorCauses":[]}
+    if let Some(error) = OktaClientError::from_okta_api_error(&text) {
+        return Err(error);
+    }
 
-    let json = match json {
+    let json = match serde_json::from_str::<serde_json::Value>(&text) {
         Ok(json) => json,
         Err(_) => {
             // Look for a HTML response with error-code, o-form-explain
             let dom = Html::parse_document(&text);
 
             // Look for <div class="error-code">
-            let error_code: String = dom
-                .select(&Selector::parse(r#"div[class="error-code"]"#).unwrap())
-                .flat_map(|element| element.text())
-                .collect();
+            let error_code = select_text(&dom, r#"div[class="error-code"]"#)?;
 
             // Look for <p class="o-form-explain">
-            let error_explain: String = dom
-                .select(&Selector::parse(r#"p[class="o-form-explain"]"#).unwrap())
-                .flat_map(|element| element.text())
-                .collect();
+            let error_explain = select_text(&dom, r#"p[class="o-form-explain"]"#)?;
+
+            if error_code.is_empty() && error_explain.is_empty() {
+                return Err(OktaClientError::Parser(format!(
+                    "Unexpected response while getting the openid configuration at {}: {}",
+                    url, text
+                )));
+            }
 
-            // @todo return a nice error if code and explain are missing
-            return Err(OktaClientError::Parser(format!(
+            return Err(OktaClientError::OktaAPI(format!(
                 "Error {} while getting the openid configuration at {}: {}",
                 error_code, url, error_explain
             )));
         }
     };
 
-    let token_endpoint = match json.get("token_endpoint") {
-        Some(token_endpoint) => token_endpoint.as_str().expect("token_endpoint was expected to be a string"),
-        // @todo capture JSON errors in this format:
-        // {"errorCode":"E0000007","errorSummary":"Not found: Resource not found: ABCXYZ (AuthorizationServer)","errorLink":"E0000007","errorId":"123123","errorCauses":[]}
-
-        None => return Err(OktaClientError::Parser(
-            format!("Error while getting the openid configuration at {}: token_endpoint was missing from the openid configuration: {}",url, text))),
-        };
+    serde_json::from_value::<OpenIDConfig>(json).map_err(|e| {
+        OktaClientError::Parser(format!(
+            "Error while getting the openid configuration at {}: {}",
+            url, e
+        ))
+    })
+}
 
-    let authorization_endpoint = match json.get("authorization_endpoint") {
-        Some(token_endpoint) => token_endpoint.as_str().expect("authorization_endpoint was expected to be a string"),
-        None => return Err(OktaClientError::Parser(
-        format!("Error while getting the openid configuration at {}: authorization_endpoint was missing from the openid configuration: {}", url, text))),
-        };
+fn select_text(dom: &Html, selector: &str) -> Result<String, OktaClientError> {
+    let selector = Selector::parse(selector)
+        .map_err(|e| OktaClientError::Parser(format!("Invalid selector {}: {:?}", selector, e)))?;
 
-    Ok(OpenIDConfig {
-        token_endpoint: token_endpoint.to_owned(),
-        authorization_endpoint: authorization_endpoint.to_owned(),
-    })
+    Ok(dom
+        .select(&selector)
+        .flat_map(|element| element.text())
+        .collect())
 }
@@ -0,0 +1,55 @@
+/// Change an expired password when the OKTA /api/v1/authn endpoint returns PASSWORD_EXPIRED
+use crate::okta::authn::OktaAuthnResponse;
+use crate::okta::{OktaClient, OktaClientError};
+
+use serde::Serialize;
+
+/// Asks the user for a new password and warns them before it expires, ie. on the CLI
+pub trait PasswordPrompt: Send + Sync {
+    /// The password to change to, when the current one has expired
+    fn new_password(&self) -> Result<String, OktaClientError>;
+
+    /// Called when the password will expire soon, with the days left if OKTA says
+    fn password_warning(&self, _expires_in_days: Option<u32>) {}
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OktaChangePasswordRequest {
+    state_token: String,
+    old_password: String,
+    new_password: String,
+}
+
+impl OktaClient {
+    /// Change the user's expired password, to continue signing in
+    pub async fn do_okta_change_password(
+        &self,
+        transaction: OktaAuthnResponse,
+    ) -> Result<OktaAuthnResponse, OktaClientError> {
+        let prompt = self.password_prompt.as_ref().ok_or_else(|| {
+            OktaClientError::PasswordExpired(
+                "The password has expired, but no password prompt was configured".to_owned(),
+            )
+        })?;
+
+        let state_token = transaction.state_token.ok_or_else(|| {
+            OktaClientError::Parser("PASSWORD_EXPIRED response was missing stateToken".to_owned())
+        })?;
+
+        let (_, old_password) = self.credentials()?;
+
+        let request = OktaChangePasswordRequest {
+            state_token,
+            old_password: old_password.to_owned(),
+            new_password: prompt.new_password()?,
+        };
+
+        let url = match transaction.links.next {
+            Some(next) => next.href,
+            None => self.okta_url("api/v1/authn/credentials/change_password"),
+        };
+
+        self.post_authn(&url, &request).await
+    }
+}
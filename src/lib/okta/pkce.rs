@@ -1,15 +1,15 @@
-pub struct PKCE {
+pub struct Pkce {
     pub code_verifier: String,
     pub code_challenge: String,
 }
 
-impl PKCE {
+impl Pkce {
     /// Create a new Code Verifier
     pub fn new() -> Self {
         let code_verifier = pkce::code_verifier(128);
-        PKCE {
-            code_verifier: String::from_utf8(code_verifier.clone())
-                .expect("Couldn't convert from vec to string"),
+        Pkce {
+            // The verifier only contains unreserved URL characters, so this is never lossy
+            code_verifier: String::from_utf8_lossy(&code_verifier).into_owned(),
             code_challenge: pkce::code_challenge(&code_verifier),
         }
     }
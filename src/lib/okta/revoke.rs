@@ -0,0 +1,116 @@
+/// Revoke access and refresh tokens (RFC 7009), ie. after one is leaked
+use crate::okta::client_auth::ClientAuthenticationParams;
+use crate::okta::token::OktaTokenError;
+use crate::okta::{OktaClient, OktaClientError};
+
+use serde::Serialize;
+
+/// Which kind of token is being revoked or introspected, so OKTA can find it faster
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenTypeHint {
+    AccessToken,
+    /// Revoking a refresh token also revokes the access tokens issued with it
+    RefreshToken,
+}
+
+impl TokenTypeHint {
+    pub const NAMES: [&'static str; 2] = ["access_token", "refresh_token"];
+}
+
+impl std::fmt::Display for TokenTypeHint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TokenTypeHint::AccessToken => f.write_str("access_token"),
+            TokenTypeHint::RefreshToken => f.write_str("refresh_token"),
+        }
+    }
+}
+
+impl std::str::FromStr for TokenTypeHint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "access_token" => Ok(TokenTypeHint::AccessToken),
+            "refresh_token" => Ok(TokenTypeHint::RefreshToken),
+            _ => Err(format!("Unknown token type hint {}", s)),
+        }
+    }
+}
+
+/// The form posted to the revocation and introspection endpoints
+#[derive(Serialize, Debug)]
+pub(crate) struct OktaTokenHintRequest {
+    token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_type_hint: Option<TokenTypeHint>,
+    client_id: String,
+    #[serde(flatten)]
+    client_authentication: ClientAuthenticationParams,
+}
+
+impl OktaClient {
+    /// Revoke an access or refresh token, so it can't be used again
+    /// Revoking a token that has already expired or been revoked succeeds
+    pub async fn revoke(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+    ) -> Result<(), OktaClientError> {
+        let endpoint = self
+            .openid_config
+            .revocation_endpoint
+            .as_deref()
+            .ok_or_else(|| {
+                OktaClientError::Configuration(
+                    "The authorization server doesn't publish a revocation_endpoint".to_owned(),
+                )
+            })?;
+
+        let response = self
+            .http
+            .post(endpoint)
+            .form(&self.token_hint_request(token, token_type_hint, endpoint)?)
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+
+        match status.is_success() {
+            true => Ok(()),
+            false => Err(endpoint_error(status, &text, "revoking")),
+        }
+    }
+
+    /// The token and this client's authentication, for the revocation or introspection endpoint
+    pub(crate) fn token_hint_request(
+        &self,
+        token: &str,
+        token_type_hint: Option<TokenTypeHint>,
+        endpoint: &str,
+    ) -> Result<OktaTokenHintRequest, OktaClientError> {
+        Ok(OktaTokenHintRequest {
+            token: token.to_owned(),
+            token_type_hint,
+            client_id: self.client_id.to_owned(),
+            client_authentication: self
+                .client_authentication
+                .as_params(&self.client_id, endpoint)?,
+        })
+    }
+}
+
+/// The OAuth error in a response, ie. invalid_client, otherwise the status and body
+pub(crate) fn endpoint_error(
+    status: reqwest::StatusCode,
+    text: &str,
+    action: &str,
+) -> OktaClientError {
+    match serde_json::from_str::<OktaTokenError>(text) {
+        Ok(error) => error.into(),
+        Err(_) => {
+            OktaClientError::OktaAPI(format!("{} while {} the token: {}", status, action, text))
+        }
+    }
+}
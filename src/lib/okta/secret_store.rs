@@ -0,0 +1,323 @@
+/// Store passwords and refresh tokens outside of plain text files
+use crate::okta::cache::write_private_file;
+use crate::okta::OktaClientError;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The service name secrets are stored under in the OS keyring
+#[cfg(feature = "keyring")]
+const SERVICE: &str = "auth-token";
+
+/// Length of an AES-GCM nonce, in bytes
+const NONCE_LENGTH: usize = 12;
+
+pub trait SecretStore: Send + Sync {
+    fn get(&self, name: &str) -> Result<Option<String>, OktaClientError>;
+    fn set(&self, name: &str, secret: &str) -> Result<(), OktaClientError>;
+    fn delete(&self, name: &str) -> Result<(), OktaClientError>;
+}
+
+/// The name a user's password is stored under
+/// The base URL is compared without a trailing slash, the same as in TokenCacheKey
+pub fn password_secret_name(base_url: &str, username: &str) -> String {
+    format!("password:{}:{}", base_url.trim_end_matches('/'), username)
+}
+
+/// The name the default username for a tenant is stored under
+pub fn username_secret_name(base_url: &str) -> String {
+    format!("username:{}", base_url.trim_end_matches('/'))
+}
+
+/// Use the OS keyring (Secret Service on Linux, Keychain on Mac, Credential Manager on Windows)
+/// if it can be reached, otherwise an encrypted file in the user's config directory
+pub fn default_secret_store() -> Result<Arc<dyn SecretStore>, OktaClientError> {
+    #[cfg(feature = "keyring")]
+    if KeyringStore.is_available() {
+        return Ok(Arc::new(KeyringStore));
+    }
+
+    let dir = dirs::config_dir()
+        .map(|dir| dir.join("auth-token"))
+        .ok_or_else(|| OktaClientError::SecretStore("No config directory found".to_owned()))?;
+
+    Ok(Arc::new(EncryptedFileStore::open(
+        dir.join("secrets.json"),
+        dir.join("secrets.key"),
+    )))
+}
+
+/// Secrets stored in the OS keyring
+#[cfg(feature = "keyring")]
+pub struct KeyringStore;
+
+#[cfg(feature = "keyring")]
+impl KeyringStore {
+    /// Whether the keyring can be reached, ie. there is a D-Bus session with a Secret Service
+    pub fn is_available(&self) -> bool {
+        !matches!(
+            self.entry("probe").and_then(|entry| entry.get_password()),
+            Err(keyring::Error::NoStorageAccess(_)) | Err(keyring::Error::PlatformFailure(_))
+        )
+    }
+
+    fn entry(&self, name: &str) -> keyring::Result<keyring::Entry> {
+        keyring::Entry::new(SERVICE, name)
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl SecretStore for KeyringStore {
+    fn get(&self, name: &str) -> Result<Option<String>, OktaClientError> {
+        match self.entry(name).and_then(|entry| entry.get_password()) {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(OktaClientError::SecretStore(e.to_string())),
+        }
+    }
+
+    fn set(&self, name: &str, secret: &str) -> Result<(), OktaClientError> {
+        self.entry(name)
+            .and_then(|entry| entry.set_password(secret))
+            .map_err(|e| OktaClientError::SecretStore(e.to_string()))
+    }
+
+    fn delete(&self, name: &str) -> Result<(), OktaClientError> {
+        match self.entry(name).and_then(|entry| entry.delete_password()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(OktaClientError::SecretStore(e.to_string())),
+        }
+    }
+}
+
+/// Secrets stored in a JSON file, each encrypted with AES-256-GCM
+/// The key is kept in a separate file that only the current user can read, so this protects
+/// against secrets being copied or shared with the file, not against the user's own account.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    key: EncryptionKey,
+}
+
+enum EncryptionKey {
+    Key([u8; 32]),
+    /// Read from a file, which is only created when the first secret is stored
+    File(PathBuf),
+}
+
+impl EncryptedFileStore {
+    /// Use a secrets file encrypted with the given key
+    pub fn new(path: PathBuf, key: &[u8; 32]) -> Self {
+        EncryptedFileStore {
+            path,
+            key: EncryptionKey::Key(key.to_owned()),
+        }
+    }
+
+    /// Use a secrets file encrypted with the key in key_path, creating the key when a secret is first stored
+    pub fn open(path: PathBuf, key_path: PathBuf) -> Self {
+        EncryptedFileStore {
+            path,
+            key: EncryptionKey::File(key_path),
+        }
+    }
+
+    /// The cipher for the key, or None if the key file doesn't exist and create is false
+    fn cipher(&self, create: bool) -> Result<Option<Aes256Gcm>, OktaClientError> {
+        let key = match &self.key {
+            EncryptionKey::Key(key) => key.to_owned(),
+            EncryptionKey::File(key_path) => {
+                let key = if key_path.exists() {
+                    std::fs::read(key_path).map_err(|e| file_error(key_path, e))?
+                } else if create {
+                    let key = Aes256Gcm::generate_key(OsRng).to_vec();
+                    write_private_file(key_path, &key).map_err(|e| file_error(key_path, e))?;
+                    key
+                } else {
+                    return Ok(None);
+                };
+
+                key.try_into().map_err(|_| {
+                    OktaClientError::SecretStore(format!(
+                        "{}: Invalid key length",
+                        key_path.display()
+                    ))
+                })?
+            }
+        };
+
+        Ok(Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+    }
+
+    fn load(&self) -> Result<HashMap<String, String>, OktaClientError> {
+        if !self.path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let text = std::fs::read_to_string(&self.path).map_err(|e| file_error(&self.path, e))?;
+
+        serde_json::from_str(&text).map_err(|e| file_error(&self.path, e))
+    }
+
+    fn save(&self, secrets: &HashMap<String, String>) -> Result<(), OktaClientError> {
+        let text = serde_json::to_string_pretty(secrets).map_err(|e| file_error(&self.path, e))?;
+
+        write_private_file(&self.path, text.as_bytes()).map_err(|e| file_error(&self.path, e))
+    }
+}
+
+impl SecretStore for EncryptedFileStore {
+    fn get(&self, name: &str) -> Result<Option<String>, OktaClientError> {
+        let encrypted = match self.load()?.remove(name) {
+            Some(encrypted) => base64::decode(encrypted).map_err(|e| file_error(&self.path, e))?,
+            None => return Ok(None),
+        };
+
+        if encrypted.len() < NONCE_LENGTH {
+            return Err(file_error(&self.path, format!("{} is corrupt", name)));
+        }
+
+        let cipher = self
+            .cipher(false)?
+            .ok_or_else(|| file_error(&self.path, format!("No key to decrypt {}", name)))?;
+
+        // The nonce is stored in front of the ciphertext
+        let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+        let secret = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| file_error(&self.path, format!("Could not decrypt {}", name)))?;
+
+        String::from_utf8(secret)
+            .map(Some)
+            .map_err(|e| file_error(&self.path, e))
+    }
+
+    fn set(&self, name: &str, secret: &str) -> Result<(), OktaClientError> {
+        let cipher = self
+            .cipher(true)?
+            .ok_or_else(|| file_error(&self.path, format!("No key to encrypt {}", name)))?;
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, secret.as_bytes())
+            .map_err(|_| file_error(&self.path, format!("Could not encrypt {}", name)))?;
+
+        let mut secrets = self.load()?;
+        secrets.insert(
+            name.to_owned(),
+            base64::encode([nonce.as_slice(), &ciphertext].concat()),
+        );
+
+        self.save(&secrets)
+    }
+
+    fn delete(&self, name: &str) -> Result<(), OktaClientError> {
+        let mut secrets = self.load()?;
+
+        match secrets.remove(name) {
+            Some(_) => self.save(&secrets),
+            None => Ok(()),
+        }
+    }
+}
+
+fn file_error(path: &Path, error: impl std::fmt::Display) -> OktaClientError {
+    OktaClientError::SecretStore(format!("{}: {}", path.display(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory for the files of one test, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "auth-token-test-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&dir);
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn stores_secrets_encrypted() {
+        let dir = TempDir::new("round-trip");
+        let store = EncryptedFileStore::open(dir.0.join("secrets.json"), dir.0.join("secrets.key"));
+
+        // The key is only created when a secret is stored
+        assert_eq!(store.get("password:a:b").unwrap(), None);
+        assert!(!dir.0.join("secrets.key").exists());
+
+        store.set("password:a:b", "pa$sw0rd").unwrap();
+        assert_eq!(
+            store.get("password:a:b").unwrap().as_deref(),
+            Some("pa$sw0rd")
+        );
+
+        let text = std::fs::read_to_string(dir.0.join("secrets.json")).unwrap();
+        assert!(!text.contains("pa$sw0rd"));
+
+        // Reopening reads the key that was created
+        let store = EncryptedFileStore::open(dir.0.join("secrets.json"), dir.0.join("secrets.key"));
+        assert_eq!(
+            store.get("password:a:b").unwrap().as_deref(),
+            Some("pa$sw0rd")
+        );
+
+        store.delete("password:a:b").unwrap();
+        assert_eq!(store.get("password:a:b").unwrap(), None);
+    }
+
+    #[test]
+    fn returns_none_for_missing_secrets() {
+        let dir = TempDir::new("missing");
+        let store = EncryptedFileStore::new(dir.0.join("secrets.json"), &[1; 32]);
+
+        assert_eq!(store.get("username:a").unwrap(), None);
+        store.delete("username:a").unwrap();
+
+        store.set("username:b", "my.user").unwrap();
+        assert_eq!(store.get("username:a").unwrap(), None);
+    }
+
+    #[test]
+    fn fails_to_decrypt_with_another_key() {
+        let dir = TempDir::new("wrong-key");
+        let path = dir.0.join("secrets.json");
+
+        EncryptedFileStore::new(path.to_owned(), &[1; 32])
+            .set("username:a", "my.user")
+            .unwrap();
+
+        assert!(matches!(
+            EncryptedFileStore::new(path, &[2; 32]).get("username:a"),
+            Err(OktaClientError::SecretStore(_))
+        ));
+    }
+
+    #[test]
+    fn names_secrets_without_a_trailing_slash() {
+        assert_eq!(
+            username_secret_name("https://myapp.okta.com/"),
+            username_secret_name("https://myapp.okta.com")
+        );
+        assert_eq!(
+            password_secret_name("https://myapp.okta.com/", "my.user"),
+            password_secret_name("https://myapp.okta.com", "my.user")
+        );
+    }
+}
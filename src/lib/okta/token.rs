@@ -1,5 +1,6 @@
 /// Call the OAuth token endpoint
-use crate::okta::OktaClient;
+use crate::okta::client_auth::ClientAuthenticationParams;
+use crate::okta::{OktaClient, OktaClientError};
 
 use serde::{Deserialize, Serialize};
 
@@ -8,49 +9,192 @@ use serde::{Deserialize, Serialize};
 pub enum GrantType {
     Implicit, // Not Implemented
     AuthorizationCode,
-    ClientCredentials, // Not Implemented
-    Password,          // Not Implemented
-    RefreshToken,      // Not Implemented
+    ClientCredentials,
+    Password,
+    RefreshToken,
+    /// From the OKTA Identity Engine (IDX) API
+    InteractionCode,
+    /// From the Device Authorization grant (RFC 8628)
+    #[serde(rename = "urn:ietf:params:oauth:grant-type:device_code")]
+    DeviceCode,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OktaTokenRequest {
     pub client_id: String,
-    pub code_verifier: String,
-    pub redirect_uri: String,
     pub grant_type: GrantType,
-    pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_verifier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interaction_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_code: Option<String>,
+    #[serde(flatten)]
+    pub client_authentication: ClientAuthenticationParams,
+}
+
+impl OktaTokenRequest {
+    pub fn new(client_id: String, grant_type: GrantType) -> Self {
+        OktaTokenRequest {
+            client_id,
+            grant_type,
+            code_verifier: None,
+            redirect_uri: None,
+            code: None,
+            refresh_token: None,
+            scope: None,
+            username: None,
+            password: None,
+            interaction_code: None,
+            device_code: None,
+            client_authentication: ClientAuthenticationParams::default(),
+        }
+    }
+}
+
+/// The tokens returned by the OAuth token endpoint
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TokenResponse {
+    pub token_type: String,
+    /// Empty if only an ID token was requested, ie. with ResponseType::IdToken
+    pub access_token: String,
+    /// Lifetime of the access token, in seconds
+    pub expires_in: u64,
+    #[serde(default)]
+    pub scope: String,
+    pub id_token: Option<String>,
+    /// Only returned if the offline_access scope was requested
+    pub refresh_token: Option<String>,
+}
+
+/// An OAuth error from the token endpoint, ie. invalid_grant or authorization_pending
+#[derive(Deserialize, Debug)]
+pub(crate) struct OktaTokenError {
+    pub error: String,
+    pub error_description: Option<String>,
+}
+
+impl From<OktaTokenError> for OktaClientError {
+    fn from(error: OktaTokenError) -> Self {
+        OktaClientError::OktaAPI(format!(
+            "{}: {}",
+            error.error,
+            error.error_description.unwrap_or_default()
+        ))
+    }
 }
 
 impl OktaClient {
-    /// Use an auth code to get an access token
-    pub async fn do_oauth_token(&self, auth_code: String) -> Result<String, String> {
-        let request = OktaTokenRequest {
-            client_id: self.client_id.to_owned(),
-            code_verifier: self.pkce.code_verifier.to_owned(),
-            redirect_uri: self.login_redirect_url.to_owned(),
-            grant_type: GrantType::AuthorizationCode,
-            code: auth_code.to_owned(),
-        };
-
-        let client = reqwest::Client::new();
-
-        let req = client
-            .post(&self.token_endpoint)
+    /// Use an auth code to get the tokens
+    pub async fn do_oauth_token(
+        &self,
+        auth_code: String,
+    ) -> Result<TokenResponse, OktaClientError> {
+        let mut request =
+            OktaTokenRequest::new(self.client_id.to_owned(), GrantType::AuthorizationCode);
+        request.code_verifier = Some(self.pkce.code_verifier.to_owned());
+        request.redirect_uri = Some(self.login_redirect_url()?.to_owned());
+        request.code = Some(auth_code);
+
+        self.post_token_request(request).await
+    }
+
+    /// Use a refresh token to get a new set of tokens
+    pub async fn do_oauth_refresh_token(
+        &self,
+        refresh_token: String,
+    ) -> Result<TokenResponse, OktaClientError> {
+        let mut request = OktaTokenRequest::new(self.client_id.to_owned(), GrantType::RefreshToken);
+        request.refresh_token = Some(refresh_token);
+
+        // Omitting the scope keeps the scopes of the original grant
+        if !self.scopes.is_empty() {
+            request.scope = Some(self.scopes.to_owned());
+        }
+
+        self.post_token_request(request).await
+    }
+
+    /// Use a username and password to get the tokens, without a session or auth code
+    pub async fn do_oauth_password(&self) -> Result<TokenResponse, OktaClientError> {
+        let (username, password) = self.credentials()?;
+
+        let mut request = OktaTokenRequest::new(self.client_id.to_owned(), GrantType::Password);
+        request.username = Some(username.to_owned());
+        request.password = Some(password.to_owned());
+        request.scope = Some(self.scopes.to_owned());
+
+        self.post_token_request(request).await
+    }
+
+    /// Use an interaction code from the IDX API to get the tokens
+    pub async fn do_oauth_interaction_code(
+        &self,
+        interaction_code: String,
+    ) -> Result<TokenResponse, OktaClientError> {
+        let mut request =
+            OktaTokenRequest::new(self.client_id.to_owned(), GrantType::InteractionCode);
+        request.code_verifier = Some(self.pkce.code_verifier.to_owned());
+        request.interaction_code = Some(interaction_code);
+
+        self.post_token_request(request).await
+    }
+
+    /// Use the client's own credentials to get an access token
+    pub async fn do_oauth_client_credentials(&self) -> Result<TokenResponse, OktaClientError> {
+        let mut request =
+            OktaTokenRequest::new(self.client_id.to_owned(), GrantType::ClientCredentials);
+        request.scope = Some(self.scopes.to_owned());
+
+        self.post_token_request(request).await
+    }
+
+    async fn post_token_request(
+        &self,
+        request: OktaTokenRequest,
+    ) -> Result<TokenResponse, OktaClientError> {
+        Ok(self.send_token_request(request).await??)
+    }
+
+    /// Post a token request, returning OAuth errors separately so the caller can handle them
+    pub(crate) async fn send_token_request(
+        &self,
+        mut request: OktaTokenRequest,
+    ) -> Result<Result<TokenResponse, OktaTokenError>, OktaClientError> {
+        request.client_authentication = self
+            .client_authentication
+            .as_params(&self.client_id, &self.openid_config.token_endpoint)?;
+
+        let req = self
+            .http
+            .post(&self.openid_config.token_endpoint)
             .form(&request)
             .send()
-            .await;
-
-        let json = req
-            .expect("Error getting Access Token")
-            .json::<std::collections::HashMap<String, serde_json::Value>>()
-            .await;
+            .await?;
 
-        let json = json.expect("Invalid JSON");
+        let text = req.text().await?;
 
-        Ok(json["access_token"]
-            .as_str()
-            .expect("Missing access token")
-            .to_owned())
+        match serde_json::from_str::<TokenResponse>(&text) {
+            Ok(tokens) => Ok(Ok(tokens)),
+            Err(_) => match serde_json::from_str::<OktaTokenError>(&text) {
+                Ok(error) => Ok(Err(error)),
+                Err(e) => Err(OktaClientError::Parser(format!(
+                    "Invalid token response ({}): {}",
+                    e, text
+                ))),
+            },
+        }
     }
 }
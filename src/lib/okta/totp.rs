@@ -0,0 +1,146 @@
+/// Generate TOTP passcodes (RFC 6238) from a base32 seed, ie. for CI test accounts
+use crate::okta::OktaClientError;
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// OKTA only enrolls 6 digit, 30 second, HMAC-SHA1 TOTP factors
+const DIGITS: u32 = 6;
+const PERIOD: u64 = 30;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Clone)]
+pub struct Totp {
+    secret: Vec<u8>,
+}
+
+impl Totp {
+    /// The seed shown as text when enrolling the factor, spaces and padding are ignored
+    pub fn from_base32(seed: &str) -> Result<Self, OktaClientError> {
+        let secret = decode_base32(seed).ok_or_else(|| {
+            OktaClientError::Configuration("The TOTP seed is not valid base32".to_owned())
+        })?;
+
+        if secret.is_empty() {
+            return Err(OktaClientError::Configuration(
+                "The TOTP seed is empty".to_owned(),
+            ));
+        }
+
+        Ok(Totp { secret })
+    }
+
+    /// The passcode for the current time
+    pub fn now(&self) -> String {
+        let unix_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+
+        self.at(unix_time)
+    }
+
+    /// The passcode for a time, in seconds since the unix epoch
+    pub fn at(&self, unix_time: u64) -> String {
+        let mut mac =
+            Hmac::<Sha1>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(&(unix_time / PERIOD).to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+
+        // Dynamic truncation (RFC 4226 section 5.3)
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+
+        format!(
+            "{:0width$}",
+            binary % 10u32.pow(DIGITS),
+            width = DIGITS as usize
+        )
+    }
+}
+
+impl std::fmt::Debug for Totp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Totp { .. }")
+    }
+}
+
+fn decode_base32(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in text
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=' && *c != '-')
+    {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())?;
+
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The SHA-1 seed of the RFC 6238 test vectors, "12345678901234567890"
+    const RFC_6238_SEED: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn matches_the_rfc_6238_test_vectors() {
+        let totp = Totp::from_base32(RFC_6238_SEED).unwrap();
+
+        // The last 6 of the RFC's 8 digits
+        assert_eq!(totp.at(59), "287082");
+        assert_eq!(totp.at(1111111109), "081804");
+        assert_eq!(totp.at(1111111111), "050471");
+        assert_eq!(totp.at(1234567890), "005924");
+        assert_eq!(totp.at(2000000000), "279037");
+        assert_eq!(totp.at(20000000000), "353130");
+    }
+
+    #[test]
+    fn decodes_base32() {
+        assert_eq!(
+            decode_base32(RFC_6238_SEED).unwrap(),
+            b"12345678901234567890"
+        );
+        assert_eq!(decode_base32("MZXW6YQ=").unwrap(), b"foob");
+        assert_eq!(decode_base32("MZXW6===").unwrap(), b"foo");
+    }
+
+    #[test]
+    fn ignores_case_spaces_and_padding_in_base32() {
+        assert_eq!(
+            decode_base32("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap(),
+            b"12345678901234567890"
+        );
+        assert_eq!(decode_base32(" mzxw 6=== ").unwrap(), b"foo");
+    }
+
+    #[test]
+    fn rejects_invalid_seeds() {
+        assert!(decode_base32("MZXW1").is_none());
+        assert!(Totp::from_base32("").is_err());
+        assert!(Totp::from_base32("not base32!").is_err());
+    }
+}